@@ -0,0 +1,524 @@
+use crate::{
+    diff::{binding_name, is_connection_queue},
+    models::{
+        BindingDeclare, ExchangeBindings, ExchangeDeclare, ExchangeInfo, Policy, QueueDeclare,
+        QueueInfo, Vhost,
+    },
+    ManagementClient,
+};
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Desired state of the broker, as read from a topology file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Spec {
+    pub vhosts: Vec<Vhost>,
+    pub exchanges: Vec<ExchangeDeclare>,
+    pub queues: Vec<QueueDeclare>,
+    pub bindings: Vec<BindingDeclare>,
+    pub policies: Vec<Policy>,
+}
+
+impl Spec {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let name = path.display();
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", name, e))?;
+        toml::from_str(&contents).map_err(|e| format!("{} is not a valid spec: {}", name, e))
+    }
+
+    /// Every vhost the spec says anything about. Pruning
+    /// never reaches outside of these.
+    fn vhosts(&self) -> BTreeSet<&str> {
+        self.vhosts
+            .iter()
+            .map(|v| v.name.as_str())
+            .chain(self.exchanges.iter().map(|e| e.vhost.as_str()))
+            .chain(self.queues.iter().map(|q| q.vhost.as_str()))
+            .chain(self.bindings.iter().map(|b| b.vhost.as_str()))
+            .chain(self.policies.iter().map(|p| p.vhost.as_str()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+    /// The object exists with settings the broker won't let us
+    /// change in place. Applying is refused until it is resolved.
+    Conflict,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Action::Create => "+",
+            Action::Update => "~",
+            Action::Delete => "-",
+            Action::Conflict => "!",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The API call a step makes when applied.
+#[derive(Debug)]
+enum Operation {
+    PutVhost(String),
+    DeclareExchange(ExchangeDeclare),
+    DeleteExchange,
+    DeclareQueue(QueueDeclare),
+    DeleteQueue,
+    DeclareBinding(BindingDeclare),
+    DeleteBinding(ExchangeBindings),
+    PutPolicy(Policy),
+    DeletePolicy,
+    None,
+}
+
+#[derive(Debug)]
+pub struct Step {
+    pub action: Action,
+    pub kind: &'static str,
+    pub vhost: String,
+    pub name: String,
+    /// Why a conflicting step can't be applied.
+    pub note: Option<String>,
+    operation: Operation,
+}
+
+impl Step {
+    fn run<M: ManagementClient>(&self, client: &M) -> Result<(), String> {
+        match &self.operation {
+            Operation::PutVhost(name) => client.put_vhost(name),
+            Operation::DeclareExchange(e) => client.declare_exchange(e),
+            Operation::DeleteExchange => client.delete_exchange(&self.vhost, &self.name),
+            Operation::DeclareQueue(q) => client.declare_queue(q),
+            Operation::DeleteQueue => client.delete_queue(&self.vhost, &self.name),
+            Operation::DeclareBinding(b) => client.declare_binding(b),
+            Operation::DeleteBinding(b) => client.delete_binding(b),
+            Operation::PutPolicy(p) => client.put_policy(p),
+            Operation::DeletePolicy => client.delete_policy(&self.vhost, &self.name),
+            Operation::None => Ok(()),
+        }
+    }
+}
+
+/// Lists the settings that differ between the desired and
+/// live versions of an exchange or queue.
+fn differences(pairs: &[(&str, bool)]) -> Option<String> {
+    let fields: Vec<&str> = pairs
+        .iter()
+        .filter(|(_, same)| !same)
+        .map(|(f, _)| *f)
+        .collect();
+    if fields.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "{} differ, delete it to change them",
+            fields.join(", ")
+        ))
+    }
+}
+
+fn exchange_conflict(want: &ExchangeDeclare, have: &ExchangeInfo) -> Option<String> {
+    differences(&[
+        ("type", want.t == have.t),
+        ("durable", want.durable == have.durable),
+        ("auto_delete", want.auto_delete == have.auto_delete),
+        ("internal", want.internal == have.internal),
+        ("arguments", want.arguments == have.arguments),
+    ])
+}
+
+fn queue_conflict(want: &QueueDeclare, have: &QueueInfo) -> Option<String> {
+    differences(&[
+        ("durable", want.durable == have.durable),
+        ("auto_delete", want.auto_delete == have.auto_delete),
+        ("arguments", want.arguments == have.arguments),
+    ])
+}
+
+fn same_binding(want: &BindingDeclare, have: &ExchangeBindings) -> bool {
+    let arguments: BTreeMap<String, Value> = have
+        .arguments
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    want.vhost == have.vhost
+        && want.source == have.source
+        && want.destination == have.dest
+        && want.destination_type == have.dest_type
+        && want.routing_key == have.routing_key
+        && want.arguments == arguments
+}
+
+fn same_policy(want: &Policy, have: &Policy) -> bool {
+    want.pattern == have.pattern
+        && want.apply_to == have.apply_to
+        && want.priority == have.priority
+        && want.definition == have.definition
+}
+
+/// What the broker has of everything a spec can declare.
+struct Live {
+    vhosts: Vec<Vhost>,
+    exchanges: Vec<ExchangeInfo>,
+    queues: Vec<QueueInfo>,
+    bindings: Vec<ExchangeBindings>,
+    policies: Vec<Policy>,
+}
+
+/// Works out the steps needed to converge the broker to the spec.
+pub fn plan<M>(client: &M, spec: &Spec, prune: bool) -> Result<Vec<Step>, String>
+where
+    M: ManagementClient,
+{
+    let live = Live {
+        vhosts: client.get_vhosts()?,
+        exchanges: client.get_exchange_overview()?,
+        queues: client.get_queues_info()?,
+        bindings: client.get_bindings()?,
+        policies: client.get_policies()?,
+    };
+    Ok(steps(spec, live, prune))
+}
+
+/// Creates and updates come first, in dependency order, followed
+/// by deletes when pruning. Pruning only touches vhosts named in
+/// the spec, never deletes vhosts and leaves built-in `amq.*`
+/// exchanges and queues owned by a connection alone.
+fn steps(spec: &Spec, live: Live, prune: bool) -> Vec<Step> {
+    let Live {
+        vhosts,
+        exchanges,
+        queues,
+        bindings,
+        policies,
+    } = live;
+    let managed = spec.vhosts();
+    let step = |action, kind, vhost: &str, name: &str, operation| Step {
+        action,
+        kind,
+        vhost: vhost.to_string(),
+        name: name.to_string(),
+        note: None,
+        operation,
+    };
+
+    let mut steps = Vec::new();
+    for v in &spec.vhosts {
+        if !vhosts.contains(v) {
+            steps.push(step(
+                Action::Create,
+                "vhost",
+                "",
+                &v.name,
+                Operation::PutVhost(v.name.clone()),
+            ));
+        }
+    }
+    for want in &spec.exchanges {
+        match exchanges
+            .iter()
+            .find(|e| e.vhost == want.vhost && e.name == want.name)
+        {
+            None => steps.push(step(
+                Action::Create,
+                "exchange",
+                &want.vhost,
+                &want.name,
+                Operation::DeclareExchange(want.clone()),
+            )),
+            Some(have) => {
+                if let Some(note) = exchange_conflict(want, have) {
+                    let mut s = step(
+                        Action::Conflict,
+                        "exchange",
+                        &want.vhost,
+                        &want.name,
+                        Operation::None,
+                    );
+                    s.note = Some(note);
+                    steps.push(s);
+                }
+            }
+        }
+    }
+    for want in &spec.queues {
+        match queues
+            .iter()
+            .find(|q| q.vhost == want.vhost && q.name == want.name)
+        {
+            None => steps.push(step(
+                Action::Create,
+                "queue",
+                &want.vhost,
+                &want.name,
+                Operation::DeclareQueue(want.clone()),
+            )),
+            Some(have) => {
+                if let Some(note) = queue_conflict(want, have) {
+                    let mut s = step(
+                        Action::Conflict,
+                        "queue",
+                        &want.vhost,
+                        &want.name,
+                        Operation::None,
+                    );
+                    s.note = Some(note);
+                    steps.push(s);
+                }
+            }
+        }
+    }
+    for want in &spec.bindings {
+        if !bindings.iter().any(|b| same_binding(want, b)) {
+            let name = binding_name(
+                &want.source,
+                &want.destination_type,
+                &want.destination,
+                &want.routing_key,
+            );
+            steps.push(step(
+                Action::Create,
+                "binding",
+                &want.vhost,
+                &name,
+                Operation::DeclareBinding(want.clone()),
+            ));
+        }
+    }
+    for want in &spec.policies {
+        let existing = policies
+            .iter()
+            .find(|p| p.vhost == want.vhost && p.name == want.name);
+        let action = match existing {
+            None => Action::Create,
+            Some(have) if !same_policy(want, have) => Action::Update,
+            Some(_) => continue,
+        };
+        steps.push(step(
+            action,
+            "policy",
+            &want.vhost,
+            &want.name,
+            Operation::PutPolicy(want.clone()),
+        ));
+    }
+
+    if !prune {
+        return steps;
+    }
+    for have in &bindings {
+        // Bindings from the default exchange are implicit.
+        if !managed.contains(have.vhost.as_str()) || have.source.is_empty() {
+            continue;
+        }
+        if !spec.bindings.iter().any(|b| same_binding(b, have)) {
+            let name = binding_name(&have.source, &have.dest_type, &have.dest, &have.routing_key);
+            steps.push(step(
+                Action::Delete,
+                "binding",
+                &have.vhost,
+                &name,
+                Operation::DeleteBinding(have.clone()),
+            ));
+        }
+    }
+    for have in &policies {
+        if managed.contains(have.vhost.as_str())
+            && !spec
+                .policies
+                .iter()
+                .any(|p| p.vhost == have.vhost && p.name == have.name)
+        {
+            steps.push(step(
+                Action::Delete,
+                "policy",
+                &have.vhost,
+                &have.name,
+                Operation::DeletePolicy,
+            ));
+        }
+    }
+    for have in &queues {
+        if managed.contains(have.vhost.as_str())
+            && !have.name.starts_with("amq.")
+            && !is_connection_queue(have.exclusive, &have.name)
+            && !spec
+                .queues
+                .iter()
+                .any(|q| q.vhost == have.vhost && q.name == have.name)
+        {
+            steps.push(step(
+                Action::Delete,
+                "queue",
+                &have.vhost,
+                &have.name,
+                Operation::DeleteQueue,
+            ));
+        }
+    }
+    for have in &exchanges {
+        if managed.contains(have.vhost.as_str())
+            && !have.name.is_empty()
+            && !have.name.starts_with("amq.")
+            && !spec
+                .exchanges
+                .iter()
+                .any(|e| e.vhost == have.vhost && e.name == have.name)
+        {
+            steps.push(step(
+                Action::Delete,
+                "exchange",
+                &have.vhost,
+                &have.name,
+                Operation::DeleteExchange,
+            ));
+        }
+    }
+    steps
+}
+
+fn print_step(s: &Step, kind_w: usize, vhost_w: usize) {
+    let note = match &s.note {
+        Some(n) => format!("  ({})", n),
+        None => String::new(),
+    };
+    println!(
+        "{} {:<kw$}  {:<vw$}  {}{}",
+        s.action,
+        s.kind,
+        s.vhost,
+        s.name,
+        note,
+        kw = kind_w,
+        vw = vhost_w
+    );
+}
+
+fn count(steps: &[Step], action: Action) -> usize {
+    steps.iter().filter(|s| s.action == action).count()
+}
+
+/// Prints the plan, or applies it step by step unless `dry_run`
+/// is set. Nothing is applied if any step conflicts, and
+/// applying stops at the first failure.
+pub fn apply<M>(client: &M, steps: &[Step], dry_run: bool) -> Result<(), String>
+where
+    M: ManagementClient,
+{
+    if steps.is_empty() {
+        println!("Broker already matches the spec.");
+        return Ok(());
+    }
+    let kind_w = steps.iter().map(|s| s.kind.len()).max().unwrap_or(0);
+    let vhost_w = steps.iter().map(|s| s.vhost.len()).max().unwrap_or(0);
+    let conflicts = count(steps, Action::Conflict);
+    if dry_run || conflicts > 0 {
+        for s in steps {
+            print_step(s, kind_w, vhost_w);
+        }
+    } else {
+        for s in steps {
+            print_step(s, kind_w, vhost_w);
+            s.run(client)
+                .map_err(|e| format!("Failed to apply {} {}: {}", s.kind, s.name, e))?;
+        }
+    }
+    let (create, update, delete) = if dry_run || conflicts > 0 {
+        ("to create", "to update", "to delete")
+    } else {
+        ("created", "updated", "deleted")
+    };
+    println!(
+        "{} {}, {} {}, {} {}.",
+        count(steps, Action::Create),
+        create,
+        count(steps, Action::Update),
+        update,
+        count(steps, Action::Delete),
+        delete
+    );
+    if conflicts > 0 {
+        return Err(format!(
+            "{} objects conflict with the spec, nothing was applied.",
+            conflicts
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(name: &str, exclusive: bool) -> QueueInfo {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "vhost": "/",
+            "type": "classic",
+            "state": "running",
+            "messages_ready": 0,
+            "messages_unacknowledged": 0,
+            "messages": 0,
+            "exclusive": exclusive,
+        }))
+        .unwrap()
+    }
+
+    fn live(queues: Vec<QueueInfo>) -> Live {
+        Live {
+            vhosts: vec![Vhost {
+                name: "/".to_string(),
+            }],
+            exchanges: Vec::new(),
+            queues,
+            bindings: Vec::new(),
+            policies: Vec::new(),
+        }
+    }
+
+    fn spec() -> Spec {
+        toml::from_str("[[queues]]\nvhost = \"/\"\nname = \"orders\"\n").unwrap()
+    }
+
+    fn deleted(steps: &[Step]) -> Vec<&str> {
+        steps
+            .iter()
+            .filter(|s| s.action == Action::Delete)
+            .map(|s| s.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn prune_leaves_connection_queues_alone() {
+        let queues = vec![
+            queue("orders", false),
+            queue("old", false),
+            queue("reply-to", true),
+            queue("amq.gen-JzTY20BRgKO", false),
+        ];
+        let steps = steps(&spec(), live(queues), true);
+        assert_eq!(deleted(&steps), ["old"]);
+    }
+
+    #[test]
+    fn nothing_deleted_without_prune() {
+        let steps = steps(&spec(), live(vec![queue("old", false)]), false);
+        assert!(steps.iter().any(|s| s.action == Action::Create));
+        assert!(deleted(&steps).is_empty());
+    }
+}
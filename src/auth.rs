@@ -0,0 +1,141 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Deserialize;
+
+/// Tokens are fetched again this long before they expire, so a
+/// request never goes out with one that lapses on the way.
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// Where bearer tokens for the management API come from.
+#[derive(Debug, Clone)]
+pub enum TokenSource {
+    Static(String),
+    /// File holding the token, read again whenever it changes.
+    File(PathBuf),
+    /// OAuth 2 client credentials grant against a token endpoint.
+    ClientCredentials {
+        endpoint: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+struct Token {
+    value: String,
+    /// When to fetch a new token, for ones that expire.
+    refresh_at: Option<Instant>,
+    /// Modification time of the file the token was read from.
+    modified: Option<SystemTime>,
+}
+
+/// A bearer token, cached between requests.
+pub struct Bearer {
+    source: TokenSource,
+    cached: Mutex<Option<Token>>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Bearer {
+    /// Fails when a token file can't be read, so a wrong path
+    /// shows up on startup rather than as failing requests.
+    pub fn new(source: TokenSource) -> Result<Self, String> {
+        let bearer = Self {
+            source,
+            cached: Mutex::new(None),
+        };
+        if let TokenSource::File(path) = &bearer.source {
+            let token = Self::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            *bearer.cached.lock().unwrap() = Some(token);
+        }
+        Ok(bearer)
+    }
+
+    fn read(path: &PathBuf) -> Result<Token, std::io::Error> {
+        Ok(Token {
+            value: fs::read_to_string(path)?.trim().to_string(),
+            refresh_at: None,
+            modified: modified(path),
+        })
+    }
+
+    fn fetch(
+        client: &reqwest::blocking::Client,
+        endpoint: &str,
+        form: &[(&str, &str)],
+    ) -> Result<Token, reqwest::Error> {
+        let resp: TokenResponse = client
+            .post(endpoint)
+            .form(form)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let refresh_at = resp
+            .expires_in
+            .map(|secs| Instant::now() + Duration::from_secs(secs).saturating_sub(REFRESH_MARGIN));
+        Ok(Token {
+            value: resp.access_token,
+            refresh_at,
+            modified: None,
+        })
+    }
+
+    /// The current token, fetching or reading a new one when the
+    /// cached one is about to expire or its file has changed.
+    pub fn token(&self, client: &reqwest::blocking::Client) -> Result<String, reqwest::Error> {
+        // Held while fetching so concurrent requests share one refresh.
+        let mut cached = self.cached.lock().unwrap();
+        match &self.source {
+            TokenSource::Static(t) => return Ok(t.clone()),
+            TokenSource::File(path) => {
+                let stale = match cached.as_ref() {
+                    Some(t) => t.modified != modified(path),
+                    None => true,
+                };
+                // A file being rewritten keeps the previous token.
+                if stale {
+                    if let Ok(t) = Self::read(path) {
+                        *cached = Some(t);
+                    }
+                }
+            }
+            TokenSource::ClientCredentials {
+                endpoint,
+                client_id,
+                client_secret,
+                scope,
+            } => {
+                let expired = match cached.as_ref() {
+                    Some(t) => t.refresh_at.map(|r| Instant::now() >= r).unwrap_or(false),
+                    None => true,
+                };
+                if expired {
+                    let mut form = vec![
+                        ("grant_type", "client_credentials"),
+                        ("client_id", client_id),
+                        ("client_secret", client_secret),
+                    ];
+                    if let Some(s) = scope {
+                        form.push(("scope", s));
+                    }
+                    *cached = Some(Self::fetch(client, endpoint, &form)?);
+                }
+            }
+        }
+        Ok(cached.as_ref().map(|t| t.value.clone()).unwrap_or_default())
+    }
+}
@@ -0,0 +1,231 @@
+use crate::models::QueueInfo;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Counters of one queue when the baseline was taken. Files in the
+/// old `name=count` format have no vhost and only `deliver_get`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueCounters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vhost: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub publish: Option<u64>,
+    pub deliver_get: u64,
+    #[serde(default)]
+    pub ack: Option<u64>,
+}
+
+/// Message counters of every queue at a point in time, which
+/// snapshot mode reports progress against.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Baseline {
+    /// Seconds since the Unix epoch.
+    pub taken_at: u64,
+    queues: Vec<QueueCounters>,
+    /// Position of each queue in `queues`, by vhost and name.
+    #[serde(skip)]
+    index: HashMap<(Option<String>, String), usize>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Baseline {
+    fn new(taken_at: u64, queues: Vec<QueueCounters>) -> Self {
+        let index = queues
+            .iter()
+            .enumerate()
+            .map(|(i, c)| ((c.vhost.clone(), c.name.clone()), i))
+            .collect();
+        Self {
+            taken_at,
+            queues,
+            index,
+        }
+    }
+
+    /// Records the current counters of the queues.
+    pub fn take(queues: &[QueueInfo]) -> Self {
+        Self::new(
+            now(),
+            queues
+                .iter()
+                .map(|q| QueueCounters {
+                    vhost: Some(q.vhost.clone()),
+                    name: q.name.clone(),
+                    publish: Some(q.message_stats.publish),
+                    deliver_get: q.message_stats.deliver_get,
+                    ack: Some(q.message_stats.ack),
+                })
+                .collect(),
+        )
+    }
+
+    /// Reads a baseline file, falling back to the old `name=count`
+    /// format. Returns None when there is no usable baseline.
+    pub fn read(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        if let Ok(baseline) = serde_json::from_str::<Self>(&contents) {
+            return Some(Self::new(baseline.taken_at, baseline.queues));
+        }
+        // Old files have no timestamp, the last write is the closest.
+        let taken_at = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_else(now);
+        let queues: Vec<QueueCounters> = contents
+            .lines()
+            .filter_map(|line| line.rsplit_once('='))
+            .filter_map(|(name, val)| {
+                val.parse::<u64>().ok().map(|n| QueueCounters {
+                    vhost: None,
+                    name: name.to_string(),
+                    publish: None,
+                    deliver_get: n,
+                    ack: None,
+                })
+            })
+            .collect();
+        if queues.is_empty() {
+            None
+        } else {
+            Some(Self::new(taken_at, queues))
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Loads the baseline at `path`. When there is none, or when
+    /// `reset` is set, the current counters are saved and used.
+    pub fn resolve(path: &Path, reset: bool, queues: &[QueueInfo]) -> Self {
+        if !reset {
+            if let Some(baseline) = Self::read(path) {
+                return baseline;
+            }
+        }
+        let baseline = Self::take(queues);
+        if let Err(e) = baseline.save(path) {
+            eprintln!("Unable to save baseline: {}", e);
+        }
+        baseline
+    }
+
+    /// Counters of a queue, matched on vhost and name. Entries of
+    /// old files have no vhost and match on name alone.
+    pub fn get(&self, q: &QueueInfo) -> Option<&QueueCounters> {
+        self.index
+            .get(&(Some(q.vhost.clone()), q.name.clone()))
+            .or_else(|| self.index.get(&(None, q.name.clone())))
+            .map(|&i| &self.queues[i])
+    }
+
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.taken_at))
+    }
+}
+
+/// Formats a duration with its two largest units, e.g. `2h 05m`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let (d, h, m, s) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if d > 0 {
+        format!("{}d {:02}h", d, h)
+    } else if h > 0 {
+        format!("{}h {:02}m", h, m)
+    } else if m > 0 {
+        format!("{}m {:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(vhost: &str, name: &str) -> QueueInfo {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "vhost": vhost,
+            "type": "classic",
+            "state": "running",
+            "messages_ready": 0,
+            "messages_unacknowledged": 0,
+            "messages": 0,
+        }))
+        .unwrap()
+    }
+
+    fn counters(vhost: Option<&str>, name: &str, deliver_get: u64) -> QueueCounters {
+        QueueCounters {
+            vhost: vhost.map(String::from),
+            name: name.to_string(),
+            publish: None,
+            deliver_get,
+            ack: None,
+        }
+    }
+
+    fn read_file(name: &str, contents: &str) -> Option<Baseline> {
+        let path = std::env::temp_dir().join(format!("rabbitui-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let baseline = Baseline::read(&path);
+        let _ = fs::remove_file(&path);
+        baseline
+    }
+
+    #[test]
+    fn matches_on_vhost_and_name() {
+        let baseline = Baseline::new(
+            0,
+            vec![counters(Some("a"), "q", 1), counters(Some("b"), "q", 2)],
+        );
+        assert_eq!(baseline.get(&queue("b", "q")).unwrap().deliver_get, 2);
+        assert!(baseline.get(&queue("c", "q")).is_none());
+    }
+
+    #[test]
+    fn entries_without_vhost_match_any() {
+        let baseline = Baseline::new(0, vec![counters(None, "q", 3)]);
+        assert_eq!(baseline.get(&queue("a", "q")).unwrap().deliver_get, 3);
+    }
+
+    #[test]
+    fn reads_old_format() {
+        let baseline = read_file("old", "q1=5\nbad line\nq2=x\nq=3=7\n").unwrap();
+        assert_eq!(baseline.get(&queue("/", "q1")).unwrap().deliver_get, 5);
+        assert_eq!(baseline.get(&queue("/", "q=3")).unwrap().deliver_get, 7);
+        assert!(baseline.get(&queue("/", "q2")).is_none());
+    }
+
+    #[test]
+    fn reads_json_and_indexes_it() {
+        let saved = Baseline::new(42, vec![counters(Some("/"), "q", 9)]);
+        let json = serde_json::to_string(&saved).unwrap();
+        let baseline = read_file("json", &json).unwrap();
+        assert_eq!(baseline.taken_at, 42);
+        assert_eq!(baseline.get(&queue("/", "q")).unwrap().deliver_get, 9);
+    }
+
+    #[test]
+    fn nothing_usable_is_none() {
+        assert!(read_file("empty", "no counters here\n").is_none());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{models::QueueInfo, ManagementClient};
+
+use std::cmp::Reverse;
+
+/// Health endpoints under `/api/health/checks` that are run on
+/// every check. Ones needing arguments are added separately.
+const BROKER_CHECKS: [&str; 4] = [
+    "alarms",
+    "local-alarms",
+    "virtual-hosts",
+    "node-is-quorum-critical",
+];
+
+/// Queues listed by name in a summary before the rest are counted.
+const MAX_LISTED: usize = 3;
+
+/// Nagios plugin states. Declared from best to worst so the
+/// overall state is the maximum of the individual ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok,
+    Warning,
+    Unknown,
+    Critical,
+}
+
+impl Status {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+            Status::Unknown => 3,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+pub struct CheckResult {
+    pub name: String,
+    pub status: Status,
+    pub message: String,
+}
+
+impl CheckResult {
+    fn new(name: &str, status: Status, message: String) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message,
+        }
+    }
+}
+
+/// User supplied limits on top of the broker's own health checks.
+pub struct Thresholds {
+    pub depth_warning: Option<u64>,
+    pub depth_critical: Option<u64>,
+    /// Queues, as `name` or `vhost/name`, that must have a consumer.
+    pub consumers_required: Vec<String>,
+    pub ports: Vec<u16>,
+    pub certificate_days: u32,
+}
+
+/// Runs the broker health checks followed by the threshold checks.
+pub fn run<M: ManagementClient>(client: &M, thresholds: &Thresholds) -> Vec<CheckResult> {
+    let mut endpoints: Vec<(String, String)> = BROKER_CHECKS
+        .iter()
+        .map(|c| (c.to_string(), c.to_string()))
+        .collect();
+    endpoints.push((
+        "certificate-expiration".to_string(),
+        format!(
+            "certificate-expiration/{}/days",
+            thresholds.certificate_days
+        ),
+    ));
+    for port in &thresholds.ports {
+        endpoints.push((
+            format!("port-listener {}", port),
+            format!("port-listener/{}", port),
+        ));
+    }
+    let mut results: Vec<CheckResult> = endpoints
+        .iter()
+        .map(|(name, endpoint)| match client.health_check(endpoint) {
+            Ok(None) => CheckResult::new(name, Status::Ok, "passed".to_string()),
+            Ok(Some(reason)) => CheckResult::new(name, Status::Critical, reason),
+            Err(e) => CheckResult::new(name, Status::Unknown, e),
+        })
+        .collect();
+    let checks_depth = thresholds.depth_warning.is_some() || thresholds.depth_critical.is_some();
+    if !checks_depth && thresholds.consumers_required.is_empty() {
+        return results;
+    }
+    // Every check needing the queues is unknown when they can't be read.
+    let queues = client.get_queues_info();
+    let unknown = |name: &str, e: &String| CheckResult::new(name, Status::Unknown, e.clone());
+    if checks_depth {
+        results.push(match &queues {
+            Ok(q) => check_depth(q, thresholds),
+            Err(e) => unknown("queue depth", e),
+        });
+    }
+    for wanted in &thresholds.consumers_required {
+        results.push(match &queues {
+            Ok(q) => check_consumers(q, wanted),
+            Err(e) => unknown(&format!("consumers {}", wanted), e),
+        });
+    }
+    results
+}
+
+fn check_depth(queues: &[QueueInfo], thresholds: &Thresholds) -> CheckResult {
+    let over = |limit: Option<u64>| -> Vec<&QueueInfo> {
+        let mut qs: Vec<&QueueInfo> = match limit {
+            Some(l) => queues.iter().filter(|q| q.total > l).collect(),
+            None => Vec::new(),
+        };
+        qs.sort_by_key(|q| Reverse(q.total));
+        qs
+    };
+    let critical = over(thresholds.depth_critical);
+    let warning = over(thresholds.depth_warning);
+    let (status, offenders, limit) = if !critical.is_empty() {
+        (Status::Critical, critical, thresholds.depth_critical)
+    } else if !warning.is_empty() {
+        (Status::Warning, warning, thresholds.depth_warning)
+    } else {
+        let max = queues.iter().map(|q| q.total).max().unwrap_or(0);
+        let message = format!("deepest queue has {} messages", max);
+        return CheckResult::new("queue depth", Status::Ok, message);
+    };
+    let mut listed: Vec<String> = offenders
+        .iter()
+        .take(MAX_LISTED)
+        .map(|q| format!("{}/{} has {} messages", q.vhost, q.name, q.total))
+        .collect();
+    if offenders.len() > MAX_LISTED {
+        listed.push(format!("and {} more", offenders.len() - MAX_LISTED));
+    }
+    let message = format!("{} (limit {})", listed.join(", "), limit.unwrap_or(0));
+    CheckResult::new("queue depth", status, message)
+}
+
+fn check_consumers(queues: &[QueueInfo], wanted: &str) -> CheckResult {
+    let name = format!("consumers {}", wanted);
+    let found = queues
+        .iter()
+        .find(|q| q.name == wanted || format!("{}/{}", q.vhost, q.name) == wanted);
+    match found {
+        None => CheckResult::new(&name, Status::Critical, "queue not found".to_string()),
+        Some(q) if q.consumers == 0 => CheckResult::new(
+            &name,
+            Status::Critical,
+            format!("no consumers, {} messages waiting", q.total),
+        ),
+        Some(q) => CheckResult::new(&name, Status::Ok, format!("{} consumers", q.consumers)),
+    }
+}
+
+/// Prints the results in the Nagios plugin format: a summary
+/// line naming the failing checks, then one line per check.
+/// Returns the overall status.
+pub fn report(results: &[CheckResult]) -> Status {
+    let status = results.iter().map(|r| r.status).max().unwrap_or(Status::Ok);
+    let failing: Vec<String> = results
+        .iter()
+        .filter(|r| r.status != Status::Ok)
+        .map(|r| format!("{}: {}", r.name, r.message))
+        .collect();
+    let summary = if failing.is_empty() {
+        format!("{} checks passed", results.len())
+    } else {
+        failing.join("; ")
+    };
+    let failed = results.iter().filter(|r| r.status != Status::Ok).count();
+    println!(
+        "RABBITMQ {} - {} | checks={} failed={}",
+        status.label(),
+        summary,
+        results.len(),
+        failed
+    );
+    for r in results {
+        println!("{} {}: {}", r.status.label(), r.name, r.message);
+    }
+    status
+}
+
+/// Reports a broker that couldn't be reached at all.
+pub fn unreachable(addr: &str) -> Status {
+    println!(
+        "RABBITMQ {} - unable to reach the management API at {}",
+        Status::Unknown.label(),
+        addr
+    );
+    Status::Unknown
+}
@@ -1,12 +1,20 @@
 use crate::{
+    auth::{Bearer, TokenSource},
+    config::TlsConfig,
     models::{
-        ExchangeBindings, ExchangeInfo, MQMessage, MQMessageGetBody, Overview, PayloadPost,
-        QueueInfo,
+        BindingDeclare, ChannelInfo, ConnectionInfo, ExchangeBindings, ExchangeDeclare,
+        ExchangeInfo, FederationLink, MQMessage, MQMessageGetBody, NodeInfo, Overview, Parameter,
+        PayloadPost, Permission, Policy, QueueDeclare, QueueInfo, ShovelStatus, TopicPermission,
+        User, UserUpdate, Vhost, VhostLimits,
     },
-    ManagementClient,
+    tls, ManagementClient,
 };
 
-use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+
+use reqwest::{blocking::RequestBuilder, Method};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
 
 #[allow(dead_code)] // we dont use all variants yet, but we might
 #[derive(Serialize, Debug)]
@@ -18,10 +26,14 @@ pub enum Ackmode {
     RejectRequeueFalse,
 }
 
+enum Auth {
+    Basic { user: String, pass: Option<String> },
+    Bearer(Bearer),
+}
+
 pub struct Client {
     addr: String,
-    user: String,
-    pass: Option<String>,
+    auth: Auth,
     client: reqwest::blocking::Client,
 }
 
@@ -29,20 +41,47 @@ impl Client {
     pub fn new(addr: &str, user: &str, pass: Option<String>) -> Self {
         Self {
             addr: addr.to_string(),
-            user: user.to_string(),
-            pass,
+            auth: Auth::Basic {
+                user: user.to_string(),
+                pass,
+            },
             client: reqwest::blocking::Client::new(),
         }
     }
 
-    pub fn delete(&self, endpoint: &str) {
+    /// Authenticates with bearer tokens instead of a user and password.
+    pub fn with_token(mut self, source: TokenSource) -> Result<Self, String> {
+        self.auth = Auth::Bearer(Bearer::new(source)?);
+        Ok(self)
+    }
+
+    /// Rebuilds the HTTP client with the given TLS settings.
+    pub fn with_tls(mut self, tls: &TlsConfig) -> Result<Self, String> {
+        let mut builder = tls::configure(reqwest::blocking::Client::builder(), tls)?;
+        if let Some(name) = &tls.server_name {
+            let (b, addr) = tls::server_name(builder, &self.addr, name)?;
+            builder = b;
+            self.addr = addr;
+        }
+        self.client = builder.build().map_err(|e| e.to_string())?;
+        Ok(self)
+    }
+
+    /// Starts a request carrying the credentials.
+    fn request(&self, method: Method, url: String) -> Result<RequestBuilder, reqwest::Error> {
+        let req = self.client.request(method, url);
+        Ok(match &self.auth {
+            Auth::Basic { user, pass } => req.basic_auth(user, pass.as_ref()),
+            Auth::Bearer(b) => req.bearer_auth(b.token(&self.client)?),
+        })
+    }
+
+    pub fn delete(&self, endpoint: &str) -> Result<(), reqwest::Error> {
         let url = format!("{}{}", self.addr, endpoint);
-        // TODO care about this result.
-        let _ = self
-            .client
-            .delete(url)
-            .basic_auth(&self.user, self.pass.as_ref())
-            .send();
+        self.request(Method::DELETE, url)?
+            .send()?
+            .error_for_status()?;
+        Ok(())
     }
 
     // TODO change this to Result and cover api failures!!
@@ -51,45 +90,96 @@ impl Client {
         T: DeserializeOwned,
     {
         let url = format!("{}{}", self.addr, endpoint);
-        self.client
-            .get(url)
-            .basic_auth(&self.user, self.pass.as_ref())
+        self.request(Method::GET, url)?
             .send()?
+            .error_for_status()?
             .json()
     }
 
+    /// Lists what `endpoint` returns. Brokers answer endpoints of a
+    /// disabled plugin, or parameters of an unknown component, with
+    /// a 404, which reads as an empty list. Other errors don't.
+    fn get_list<T>(&self, endpoint: &str) -> Result<Vec<T>, String>
+    where
+        T: DeserializeOwned,
+    {
+        match self.get::<Vec<T>>(endpoint) {
+            Err(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => Ok(Vec::new()),
+            res => res.map_err(|e| e.to_string()),
+        }
+    }
+
     pub fn post<T, S>(&self, endpoint: &str, body: &S) -> Result<T, reqwest::Error>
     where
         T: DeserializeOwned,
         S: Serialize,
     {
         let url = format!("{}{}", self.addr, endpoint);
-        self.client
-            .post(url)
-            .basic_auth(&self.user, self.pass.as_ref())
+        self.request(Method::POST, url)?.json(body).send()?.json()
+    }
+
+    /// Same as `put` but POSTs, for endpoints that
+    /// respond with no content.
+    pub fn post_empty<S>(&self, endpoint: &str, body: &S) -> Result<(), reqwest::Error>
+    where
+        S: Serialize,
+    {
+        let url = format!("{}{}", self.addr, endpoint);
+        self.request(Method::POST, url)?
             .json(body)
             .send()?
-            .json()
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// PUTs the body, only caring whether the API accepted it.
+    /// Creation endpoints respond with an empty body.
+    pub fn put<S>(&self, endpoint: &str, body: &S) -> Result<(), reqwest::Error>
+    where
+        S: Serialize,
+    {
+        let url = format!("{}{}", self.addr, endpoint);
+        self.request(Method::PUT, url)?
+            .json(body)
+            .send()?
+            .error_for_status()?;
+        Ok(())
     }
 }
 
 impl ManagementClient for Client {
-    fn get_exchange_overview(&self) -> Vec<ExchangeInfo> {
-        self.get::<Vec<ExchangeInfo>>("/api/exchanges").unwrap()
+    fn get_exchange_overview(&self) -> Result<Vec<ExchangeInfo>, String> {
+        self.get::<Vec<ExchangeInfo>>("/api/exchanges")
+            .map_err(|e| e.to_string())
     }
 
-    fn get_exchange_bindings(&self, exch: &ExchangeInfo) -> Vec<ExchangeBindings> {
+    fn get_exchange_bindings(&self, exch: &ExchangeInfo) -> Result<Vec<ExchangeBindings>, String> {
         let n = exch.vhost.replace("/", "%2F");
         let endpoint = format!("/api/exchanges/{}/{}/bindings/source", n, exch.name);
-        self.get::<Vec<ExchangeBindings>>(&endpoint).unwrap()
+        self.get::<Vec<ExchangeBindings>>(&endpoint)
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_bindings(&self) -> Result<Vec<ExchangeBindings>, String> {
+        self.get::<Vec<ExchangeBindings>>("/api/bindings")
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_queue_bindings(&self, queue: &QueueInfo) -> Result<Vec<ExchangeBindings>, String> {
+        let n = queue.vhost.replace("/", "%2F");
+        let endpoint = format!("/api/queues/{}/{}/bindings", n, queue.name);
+        self.get::<Vec<ExchangeBindings>>(&endpoint)
+            .map_err(|e| e.to_string())
     }
 
-    fn get_overview(&self) -> Overview {
-        self.get::<Overview>("/api/overview").unwrap()
+    fn get_overview(&self) -> Result<Overview, String> {
+        self.get::<Overview>("/api/overview")
+            .map_err(|e| e.to_string())
     }
 
-    fn get_queues_info(&self) -> Vec<QueueInfo> {
-        self.get::<Vec<QueueInfo>>("/api/queues").unwrap()
+    fn get_queues_info(&self) -> Result<Vec<QueueInfo>, String> {
+        self.get::<Vec<QueueInfo>>("/api/queues")
+            .map_err(|e| e.to_string())
     }
 
     fn post_queue_payload(&self, queue_name: String, vhost: &str, payload: String) {
@@ -100,11 +190,8 @@ impl ManagementClient for Client {
             .payload(payload);
         // TODO consider failures
         let _ = self
-            .client
-            .post(endpoint)
-            .basic_auth("guest", Some("guest"))
-            .json(&body)
-            .send();
+            .request(Method::POST, endpoint)
+            .and_then(|r| r.json(&body).send());
     }
 
     fn pop_queue_item(&self, queue_name: &str, vhost: &str) -> Option<MQMessage> {
@@ -132,6 +219,273 @@ impl ManagementClient for Client {
     fn purge_queue(&self, queue_name: &str, vhost: &str) {
         let encoded = vhost.replace("/", "%2F");
         let endpoint = format!("/api/queues/{}/{}/contents", encoded, queue_name);
-        self.delete(&endpoint);
+        // TODO care about this result.
+        let _ = self.delete(&endpoint);
+    }
+
+    fn get_policies(&self) -> Result<Vec<Policy>, String> {
+        self.get::<Vec<Policy>>("/api/policies")
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_operator_policies(&self) -> Result<Vec<Policy>, String> {
+        self.get::<Vec<Policy>>("/api/operator-policies")
+            .map_err(|e| e.to_string())
+    }
+
+    fn put_policy(&self, policy: &Policy) -> Result<(), String> {
+        let encoded = policy.vhost.replace("/", "%2F");
+        let endpoint = format!("/api/policies/{}/{}", encoded, policy.name);
+        self.put(&endpoint, &policy.body())
+            .map_err(|e| e.to_string())
+    }
+
+    fn delete_policy(&self, vhost: &str, name: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/policies/{}/{}", encoded, name);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn put_operator_policy(&self, policy: &Policy) -> Result<(), String> {
+        let encoded = policy.vhost.replace("/", "%2F");
+        let endpoint = format!("/api/operator-policies/{}/{}", encoded, policy.name);
+        self.put(&endpoint, &policy.body())
+            .map_err(|e| e.to_string())
+    }
+
+    fn delete_operator_policy(&self, vhost: &str, name: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/operator-policies/{}/{}", encoded, name);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn get_users(&self) -> Result<Vec<User>, String> {
+        self.get::<Vec<User>>("/api/users")
+            .map_err(|e| e.to_string())
+    }
+
+    fn put_user(&self, user: &UserUpdate) -> Result<(), String> {
+        let endpoint = format!("/api/users/{}", user.name);
+        self.put(&endpoint, user).map_err(|e| e.to_string())
+    }
+
+    fn delete_user(&self, name: &str) -> Result<(), String> {
+        let endpoint = format!("/api/users/{}", name);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn get_permissions(&self) -> Result<Vec<Permission>, String> {
+        self.get::<Vec<Permission>>("/api/permissions")
+            .map_err(|e| e.to_string())
+    }
+
+    fn put_permission(&self, perm: &Permission) -> Result<(), String> {
+        let encoded = perm.vhost.replace("/", "%2F");
+        let endpoint = format!("/api/permissions/{}/{}", encoded, perm.user);
+        self.put(&endpoint, perm).map_err(|e| e.to_string())
+    }
+
+    fn delete_permission(&self, vhost: &str, user: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/permissions/{}/{}", encoded, user);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn get_topic_permissions(&self) -> Result<Vec<TopicPermission>, String> {
+        self.get::<Vec<TopicPermission>>("/api/topic-permissions")
+            .map_err(|e| e.to_string())
+    }
+
+    fn put_topic_permission(&self, perm: &TopicPermission) -> Result<(), String> {
+        let encoded = perm.vhost.replace("/", "%2F");
+        let endpoint = format!("/api/topic-permissions/{}/{}", encoded, perm.user);
+        self.put(&endpoint, perm).map_err(|e| e.to_string())
+    }
+
+    fn delete_topic_permission(&self, vhost: &str, user: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/topic-permissions/{}/{}", encoded, user);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn get_connections(&self) -> Result<Vec<ConnectionInfo>, String> {
+        self.get::<Vec<ConnectionInfo>>("/api/connections")
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_connection_counts(&self) -> Result<HashMap<String, u64>, String> {
+        #[derive(Deserialize)]
+        struct Connection {
+            vhost: String,
+        }
+        // Only the vhost column, the full list can be large.
+        let connections = self
+            .get::<Vec<Connection>>("/api/connections?columns=vhost")
+            .map_err(|e| e.to_string())?;
+        let mut counts = HashMap::new();
+        for c in connections {
+            *counts.entry(c.vhost).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    fn get_channels(&self) -> Result<Vec<ChannelInfo>, String> {
+        self.get::<Vec<ChannelInfo>>("/api/channels")
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_nodes(&self) -> Result<Vec<NodeInfo>, String> {
+        self.get::<Vec<NodeInfo>>("/api/nodes")
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_vhost_limits(&self) -> Result<Vec<VhostLimits>, String> {
+        self.get::<Vec<VhostLimits>>("/api/vhost-limits")
+            .map_err(|e| e.to_string())
+    }
+
+    fn put_vhost_limit(&self, vhost: &str, name: &str, value: i64) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/vhost-limits/{}/{}", encoded, name);
+        self.put(&endpoint, &json!({ "value": value }))
+            .map_err(|e| e.to_string())
+    }
+
+    fn delete_vhost_limit(&self, vhost: &str, name: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/vhost-limits/{}/{}", encoded, name);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn get_definitions(&self, vhost: Option<&str>) -> Result<Value, String> {
+        let endpoint = match vhost {
+            Some(v) => format!("/api/definitions/{}", v.replace("/", "%2F")),
+            None => "/api/definitions".to_string(),
+        };
+        self.get::<Value>(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn post_definitions(&self, vhost: Option<&str>, definitions: &Value) -> Result<(), String> {
+        let endpoint = match vhost {
+            Some(v) => format!("/api/definitions/{}", v.replace("/", "%2F")),
+            None => "/api/definitions".to_string(),
+        };
+        self.post_empty(&endpoint, definitions)
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_vhosts(&self) -> Result<Vec<Vhost>, String> {
+        self.get::<Vec<Vhost>>("/api/vhosts")
+            .map_err(|e| e.to_string())
+    }
+
+    fn put_vhost(&self, name: &str) -> Result<(), String> {
+        let endpoint = format!("/api/vhosts/{}", name.replace("/", "%2F"));
+        self.put(&endpoint, &json!({})).map_err(|e| e.to_string())
+    }
+
+    fn delete_vhost(&self, name: &str) -> Result<(), String> {
+        let endpoint = format!("/api/vhosts/{}", name.replace("/", "%2F"));
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn declare_exchange(&self, exchange: &ExchangeDeclare) -> Result<(), String> {
+        let encoded = exchange.vhost.replace("/", "%2F");
+        let endpoint = format!("/api/exchanges/{}/{}", encoded, exchange.name);
+        self.put(&endpoint, exchange).map_err(|e| e.to_string())
+    }
+
+    fn delete_exchange(&self, vhost: &str, name: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/exchanges/{}/{}", encoded, name);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn declare_queue(&self, queue: &QueueDeclare) -> Result<(), String> {
+        let encoded = queue.vhost.replace("/", "%2F");
+        let endpoint = format!("/api/queues/{}/{}", encoded, queue.name);
+        self.put(&endpoint, queue).map_err(|e| e.to_string())
+    }
+
+    fn delete_queue(&self, vhost: &str, name: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/queues/{}/{}", encoded, name);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn declare_binding(&self, binding: &BindingDeclare) -> Result<(), String> {
+        let encoded = binding.vhost.replace("/", "%2F");
+        let dest_type = if binding.destination_type == "exchange" {
+            "e"
+        } else {
+            "q"
+        };
+        let endpoint = format!(
+            "/api/bindings/{}/e/{}/{}/{}",
+            encoded, binding.source, dest_type, binding.destination
+        );
+        self.post_empty(&endpoint, binding)
+            .map_err(|e| e.to_string())
+    }
+
+    fn delete_binding(&self, binding: &ExchangeBindings) -> Result<(), String> {
+        let encoded = binding.vhost.replace("/", "%2F");
+        let dest_type = if binding.dest_type == "exchange" {
+            "e"
+        } else {
+            "q"
+        };
+        let endpoint = format!(
+            "/api/bindings/{}/e/{}/{}/{}/{}",
+            encoded, binding.source, dest_type, binding.dest, binding.prop_key
+        );
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn get_shovels(&self) -> Result<Vec<ShovelStatus>, String> {
+        self.get_list("/api/shovels")
+    }
+
+    fn get_parameters(&self, component: &str) -> Result<Vec<Parameter>, String> {
+        self.get_list(&format!("/api/parameters/{}", component))
+    }
+
+    fn put_parameter(&self, param: &Parameter) -> Result<(), String> {
+        let encoded = param.vhost.replace("/", "%2F");
+        let endpoint = format!(
+            "/api/parameters/{}/{}/{}",
+            param.component, encoded, param.name
+        );
+        self.put(&endpoint, param).map_err(|e| e.to_string())
+    }
+
+    fn delete_parameter(&self, component: &str, vhost: &str, name: &str) -> Result<(), String> {
+        let encoded = vhost.replace("/", "%2F");
+        let endpoint = format!("/api/parameters/{}/{}/{}", component, encoded, name);
+        self.delete(&endpoint).map_err(|e| e.to_string())
+    }
+
+    fn health_check(&self, check: &str) -> Result<Option<String>, String> {
+        let url = format!("{}/api/health/checks/{}", self.addr, check);
+        let resp = self
+            .request(Method::GET, url)
+            .and_then(|r| r.send())
+            .map_err(|e| e.to_string())?;
+        // Failing checks answer 503 with the reason in the body.
+        if resp.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            let body = resp.json::<Value>().unwrap_or_default();
+            let reason = body
+                .get("reason")
+                .and_then(|r| r.as_str())
+                .unwrap_or("check failed");
+            return Ok(Some(reason.to_string()));
+        }
+        resp.error_for_status()
+            .map(|_| None)
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_federation_links(&self) -> Result<Vec<FederationLink>, String> {
+        self.get_list("/api/federation-links")
     }
 }
@@ -1,3 +1,21 @@
+use crate::{
+    auth::TokenSource,
+    credentials::{self, PasswordSource},
+};
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+const DEFAULT_USER: &str = "guest";
+const DEFAULT_PASS: &str = "guest";
+const DEFAULT_ADDR: &str = "http://localhost:15672";
+
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub update_rate: u64,
@@ -8,3 +26,380 @@ impl Default for AppConfig {
         Self { update_rate: 2_000 }
     }
 }
+
+/// TLS settings used when talking to an `https` address.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the CAs to trust on top of the system ones.
+    pub ca_cert: Option<PathBuf>,
+    /// PEM client certificate, optionally followed by its chain.
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key of `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// PKCS#12 archive with the client certificate and key, used
+    /// instead of `client_cert` and `client_key`.
+    pub client_pkcs12: Option<PathBuf>,
+    pub pkcs12_password: Option<String>,
+    /// Host name to send in SNI and check the certificate against,
+    /// when it differs from the host of the address.
+    pub server_name: Option<String>,
+    /// Skip certificate and hostname verification.
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+/// Bearer token settings, for brokers using the OAuth 2 backend.
+/// A token is taken from a file, an environment variable or a
+/// client credentials grant, in that order.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct OAuthConfig {
+    pub token_file: Option<PathBuf>,
+    /// Name of an environment variable holding the token.
+    pub token_env: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    /// Name of an environment variable holding the client secret.
+    pub client_secret_env: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// A named broker in the config file. Anything left out falls
+/// back to the environment and then to the defaults.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub addr: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Name of an environment variable holding the password, so
+    /// it doesn't have to be written in the file.
+    pub password_env: Option<String>,
+    /// File whose first line is the password.
+    pub password_file: Option<PathBuf>,
+    /// Shell command printing the password, e.g. `pass show rabbit/prod`.
+    pub password_command: Option<String>,
+    /// Prompt for the password on startup.
+    #[serde(default)]
+    pub ask_password: bool,
+    /// Vhost used by commands taking `--vhost` when it isn't given.
+    pub vhost: Option<String>,
+    /// Milliseconds between refreshes of the TUI.
+    pub update_rate: Option<u64>,
+    pub tls: Option<TlsConfig>,
+    pub oauth: Option<OAuthConfig>,
+}
+
+impl Profile {
+    /// The first password source set, in the order the fields
+    /// are declared.
+    fn password_source(&self) -> Option<PasswordSource> {
+        if let Some(p) = &self.password {
+            Some(PasswordSource::Plain(p.clone()))
+        } else if let Some(var) = &self.password_env {
+            Some(PasswordSource::Env(var.clone()))
+        } else if let Some(path) = &self.password_file {
+            Some(PasswordSource::File(path.clone()))
+        } else if let Some(cmd) = &self.password_command {
+            Some(PasswordSource::Command(cmd.clone()))
+        } else if self.ask_password {
+            Some(PasswordSource::Prompt)
+        } else {
+            None
+        }
+    }
+}
+
+/// The contents of `~/.config/rabbitui/config.toml`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Profile used when none is picked with `--profile`.
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ConfigFile {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|h| h.join(".config").join("rabbitui").join("config.toml"))
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Reads the file given with `--config` or `RABBITUI_CONFIG`,
+    /// or the default one. Only a missing default file is allowed.
+    pub fn load(matches: &ArgMatches) -> Result<Self, String> {
+        if let Some(p) = layer(matches, Some("config"), "RABBITUI_CONFIG") {
+            return Self::read(Path::new(&p));
+        }
+        match Self::default_path() {
+            Some(p) if p.exists() => Self::read(&p),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+/// Everything needed to connect to a broker, after merging the
+/// command line, the environment, the profile and the defaults.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Profile the settings came from, if any.
+    pub profile: Option<String>,
+    pub addr: String,
+    pub user: String,
+    pub pass: String,
+    pub vhost: Option<String>,
+    pub tls: TlsConfig,
+    /// Bearer tokens to use instead of the user and password.
+    pub token: Option<TokenSource>,
+    pub app: AppConfig,
+}
+
+/// The first value set, from the command line, then the
+/// `RABBITUI_*` environment variable.
+fn layer(matches: &ArgMatches, arg: Option<&str>, var: &str) -> Option<String> {
+    arg.and_then(|a| matches.value_of(a))
+        .map(|v| v.to_string())
+        .or_else(|| env::var(var).ok())
+}
+
+fn token_source<F>(oauth: OAuthConfig, overrides: &F) -> Result<Option<TokenSource>, String>
+where
+    F: Fn(Option<&str>, &str) -> Option<String>,
+{
+    let file = overrides(Some("token-file"), "RABBITUI_TOKEN_FILE").map(PathBuf::from);
+    if let Some(path) = file.or(oauth.token_file) {
+        return Ok(Some(TokenSource::File(path)));
+    }
+    if let Some(token) = overrides(None, "RABBITUI_TOKEN") {
+        return Ok(Some(TokenSource::Static(token)));
+    }
+    if let Some(var) = oauth.token_env {
+        return env::var(&var)
+            .map(|t| Some(TokenSource::Static(t)))
+            .map_err(|_| format!("token_env {} is not set", var));
+    }
+    let endpoint = match overrides(Some("token-endpoint"), "RABBITUI_TOKEN_ENDPOINT")
+        .or(oauth.token_endpoint)
+    {
+        Some(e) => e,
+        None => return Ok(None),
+    };
+    let client_id = overrides(Some("client-id"), "RABBITUI_CLIENT_ID")
+        .or(oauth.client_id)
+        .ok_or("the token endpoint needs a client id")?;
+    let client_secret = match overrides(None, "RABBITUI_CLIENT_SECRET").or(oauth.client_secret) {
+        Some(s) => s,
+        None => match oauth.client_secret_env {
+            Some(var) => {
+                env::var(&var).map_err(|_| format!("client_secret_env {} is not set", var))?
+            }
+            None => return Err("the token endpoint needs a client secret".to_string()),
+        },
+    };
+    Ok(Some(TokenSource::ClientCredentials {
+        endpoint,
+        client_id,
+        client_secret,
+        scope: overrides(None, "RABBITUI_TOKEN_SCOPE").or(oauth.scope),
+    }))
+}
+
+impl Settings {
+    /// Settings to start with, from the profile picked with
+    /// `--profile`, `RABBITUI_PROFILE` or the file's default.
+    pub fn resolve(matches: &ArgMatches, file: &ConfigFile) -> Result<Self, String> {
+        let name = layer(matches, Some("profile"), "RABBITUI_PROFILE")
+            .or_else(|| file.default_profile.clone());
+        let password = if let Some(p) = matches.value_of("pass") {
+            Some(PasswordSource::Plain(p.to_string()))
+        } else if let Some(path) = matches.value_of("pass-file") {
+            Some(PasswordSource::File(PathBuf::from(path)))
+        } else if matches.is_present("ask-pass") {
+            Some(PasswordSource::Prompt)
+        } else if let Ok(p) = env::var("RABBITUI_PASS") {
+            Some(PasswordSource::Plain(p))
+        } else {
+            env::var_os("RABBITUI_PASS_FILE").map(|p| PasswordSource::File(PathBuf::from(p)))
+        };
+        let mut settings = Self::build(file, name, password, |arg, var| layer(matches, arg, var))?;
+        settings.tls.insecure |= matches.is_present("insecure");
+        Ok(settings)
+    }
+
+    /// Settings of a profile on its own. Used when switching
+    /// clusters, where the overrides given at startup would
+    /// otherwise point every profile at the same broker.
+    pub fn from_profile(file: &ConfigFile, name: &str) -> Result<Self, String> {
+        let source = file.profiles.get(name).and_then(|p| p.password_source());
+        if let Some(PasswordSource::Prompt) = source {
+            // The terminal belongs to the UI by then.
+            return Err(format!(
+                "{} asks for a password, start with --profile {}",
+                name, name
+            ));
+        }
+        Self::build(file, Some(name.to_string()), None, |_, _| None)
+    }
+
+    /// Merges a profile with the overrides. `password` is the source
+    /// given on the command line or in the environment, if any.
+    fn build<F>(
+        file: &ConfigFile,
+        name: Option<String>,
+        password: Option<PasswordSource>,
+        overrides: F,
+    ) -> Result<Self, String>
+    where
+        F: Fn(Option<&str>, &str) -> Option<String>,
+    {
+        let profile = match &name {
+            Some(n) => file
+                .profiles
+                .get(n)
+                .cloned()
+                .ok_or_else(|| format!("no profile named {} in the config file", n))?,
+            None => Profile::default(),
+        };
+        let update_rate = match overrides(Some("update-rate"), "RABBITUI_UPDATE_RATE") {
+            Some(r) => Some(
+                r.parse::<u64>()
+                    .map_err(|_| format!("update rate must be milliseconds, got {}", r))?,
+            ),
+            None => profile.update_rate,
+        };
+        let addr = overrides(Some("addr"), "RABBITUI_ADDR")
+            .or_else(|| profile.addr.clone())
+            .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+        let user = overrides(Some("user"), "RABBITUI_USER").or_else(|| profile.user.clone());
+        // ~/.netrc is only looked at when nothing else gives a password.
+        let (user, pass) = match password.or_else(|| profile.password_source()) {
+            Some(source) => {
+                let user = user.unwrap_or_else(|| DEFAULT_USER.to_string());
+                let pass = source.read(&user, &addr)?;
+                (user, pass)
+            }
+            None => match credentials::netrc(&addr, user.as_deref()) {
+                Some(creds) => creds,
+                None => (
+                    user.unwrap_or_else(|| DEFAULT_USER.to_string()),
+                    DEFAULT_PASS.to_string(),
+                ),
+            },
+        };
+        let token = token_source(profile.oauth.clone().unwrap_or_default(), &overrides)?;
+        let tls = profile.tls.clone().unwrap_or_default();
+        let path =
+            |arg, var, file: Option<PathBuf>| overrides(Some(arg), var).map(PathBuf::from).or(file);
+        Ok(Self {
+            profile: name,
+            addr,
+            user,
+            pass,
+            vhost: overrides(None, "RABBITUI_VHOST").or(profile.vhost),
+            tls: TlsConfig {
+                ca_cert: path("ca-cert", "RABBITUI_CA_CERT", tls.ca_cert),
+                client_cert: path("client-cert", "RABBITUI_CLIENT_CERT", tls.client_cert),
+                client_key: path("client-key", "RABBITUI_CLIENT_KEY", tls.client_key),
+                client_pkcs12: path("client-pkcs12", "RABBITUI_CLIENT_PKCS12", tls.client_pkcs12),
+                pkcs12_password: overrides(None, "RABBITUI_PKCS12_PASSWORD")
+                    .or(tls.pkcs12_password),
+                server_name: overrides(Some("server-name"), "RABBITUI_SERVER_NAME")
+                    .or(tls.server_name),
+                insecure: tls.insecure,
+            },
+            token,
+            app: AppConfig {
+                update_rate: update_rate.unwrap_or_else(|| AppConfig::default().update_rate),
+            },
+        })
+    }
+
+    /// How the broker is named in the UI.
+    pub fn cluster_name(&self) -> &str {
+        self.profile.as_deref().unwrap_or(&self.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    const FILE: &str = r#"
+default_profile = "prod"
+
+[profiles.prod]
+addr = "https://prod:15671"
+user = "ops"
+password = "secret"
+vhost = "shop"
+update_rate = 5000
+
+[profiles.prod.tls]
+ca_cert = "/etc/prod-ca.pem"
+"#;
+
+    /// Settings of `profile` with `vars` standing in for the
+    /// command line and the environment.
+    fn settings(profile: Option<&str>, vars: &[(&str, &str)]) -> Result<Settings, String> {
+        let file: ConfigFile = toml::from_str(FILE).unwrap();
+        let vars: HashMap<&str, &str> = vars.iter().cloned().collect();
+        let password = Some(PasswordSource::Plain("pw".to_string()));
+        let password = if profile.is_some() { None } else { password };
+        Settings::build(&file, profile.map(String::from), password, |_, var| {
+            vars.get(var).map(|v| v.to_string())
+        })
+    }
+
+    #[test]
+    fn profile_fills_in_settings() {
+        let s = settings(Some("prod"), &[]).unwrap();
+        assert_eq!(s.addr, "https://prod:15671");
+        assert_eq!((s.user.as_str(), s.pass.as_str()), ("ops", "secret"));
+        assert_eq!(s.vhost.as_deref(), Some("shop"));
+        assert_eq!(s.app.update_rate, 5000);
+        assert_eq!(s.tls.ca_cert, Some(PathBuf::from("/etc/prod-ca.pem")));
+        assert_eq!(s.cluster_name(), "prod");
+    }
+
+    #[test]
+    fn overrides_beat_profile() {
+        let vars = [
+            ("RABBITUI_ADDR", "http://tunnel:15672"),
+            ("RABBITUI_USER", "me"),
+            ("RABBITUI_UPDATE_RATE", "250"),
+            ("RABBITUI_CA_CERT", "/tmp/ca.pem"),
+        ];
+        let s = settings(Some("prod"), &vars).unwrap();
+        assert_eq!(s.addr, "http://tunnel:15672");
+        assert_eq!(s.user, "me");
+        assert_eq!(s.app.update_rate, 250);
+        assert_eq!(s.tls.ca_cert, Some(PathBuf::from("/tmp/ca.pem")));
+        assert_eq!(s.vhost.as_deref(), Some("shop"));
+    }
+
+    #[test]
+    fn defaults_without_profile() {
+        let s = settings(None, &[]).unwrap();
+        assert_eq!(s.addr, DEFAULT_ADDR);
+        assert_eq!((s.user.as_str(), s.pass.as_str()), (DEFAULT_USER, "pw"));
+        assert_eq!(s.app.update_rate, AppConfig::default().update_rate);
+        assert_eq!(s.cluster_name(), DEFAULT_ADDR);
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        assert!(settings(Some("staging"), &[]).is_err());
+        assert!(settings(None, &[("RABBITUI_UPDATE_RATE", "fast")]).is_err());
+        assert!(toml::from_str::<ConfigFile>("[profiles.a]\nport = 1\n").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::Command,
+};
+
+use termion::input::TermRead;
+
+/// Where a password is read from.
+#[derive(Debug, Clone)]
+pub enum PasswordSource {
+    Plain(String),
+    /// Name of an environment variable.
+    Env(String),
+    /// File whose first line is the password.
+    File(PathBuf),
+    /// Shell command printing the password on its first line,
+    /// e.g. `pass show rabbit/prod`.
+    Command(String),
+    /// Hidden prompt on the terminal.
+    Prompt,
+}
+
+impl PasswordSource {
+    pub fn read(&self, user: &str, addr: &str) -> Result<String, String> {
+        match self {
+            PasswordSource::Plain(p) => Ok(p.clone()),
+            PasswordSource::Env(var) => {
+                env::var(var).map_err(|_| format!("password variable {} is not set", var))
+            }
+            PasswordSource::File(path) => fs::read_to_string(path)
+                .map(|s| first_line(&s))
+                .map_err(|e| format!("{}: {}", path.display(), e)),
+            PasswordSource::Command(cmd) => run(cmd),
+            PasswordSource::Prompt => prompt(user, addr),
+        }
+    }
+}
+
+fn first_line(s: &str) -> String {
+    s.lines().next().unwrap_or("").to_string()
+}
+
+fn run(cmd: &str) -> Result<String, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .output()
+        .map_err(|e| format!("password_command {}: {}", cmd, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "password_command {} failed: {}",
+            cmd,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(first_line(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Asks for the password without echoing it. The prompt goes to
+/// stderr so it stays out of piped output.
+fn prompt(user: &str, addr: &str) -> Result<String, String> {
+    if !termion::is_tty(&io::stdin()) {
+        return Err("can't prompt for a password, stdin is not a terminal".to_string());
+    }
+    let mut stderr = io::stderr();
+    let _ = write!(stderr, "Password for {} at {}: ", user, addr);
+    let _ = stderr.flush();
+    let pass = io::stdin()
+        .read_passwd(&mut stderr)
+        .map_err(|e| e.to_string())?;
+    let _ = writeln!(stderr);
+    pass.ok_or_else(|| "no password given".to_string())
+}
+
+/// The host part of an address such as `https://host:15671/path`.
+fn host(addr: &str) -> &str {
+    let rest = addr.split_once("://").map(|(_, r)| r).unwrap_or(addr);
+    let rest = rest.split('/').next().unwrap_or(rest);
+    let rest = rest.rsplit_once('@').map(|(_, r)| r).unwrap_or(rest);
+    match rest.strip_prefix('[') {
+        // IPv6 literal.
+        Some(r) => r.split(']').next().unwrap_or(r),
+        None => rest.split(':').next().unwrap_or(rest),
+    }
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    env::var_os("NETRC")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".netrc")))
+}
+
+/// Looks up the login and password of the address's host in
+/// `~/.netrc`, or the file named by `NETRC`. A `default` entry
+/// is used when no machine matches. When `user` is given, only
+/// entries for that login are considered.
+pub fn netrc(addr: &str, user: Option<&str>) -> Option<(String, String)> {
+    let contents = fs::read_to_string(netrc_path()?).ok()?;
+    netrc_lookup(&contents, addr, user)
+}
+
+fn netrc_lookup(contents: &str, addr: &str, user: Option<&str>) -> Option<(String, String)> {
+    let host = host(addr);
+    let mut entries: Vec<(Option<String>, Option<String>, Option<String>)> = Vec::new();
+    let mut tokens = contents.split_whitespace();
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((tokens.next().map(String::from), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" | "password" => {
+                let value = tokens.next().map(String::from);
+                if let Some(entry) = entries.last_mut() {
+                    if token == "login" {
+                        entry.1 = value;
+                    } else {
+                        entry.2 = value;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let accepts = |login: &Option<String>| match (user, login) {
+        (Some(u), Some(l)) => u == l,
+        (Some(_), None) => true,
+        (None, l) => l.is_some(),
+    };
+    let matching = |machine: Option<&str>| {
+        entries
+            .iter()
+            .find(|(m, l, p)| m.as_deref() == machine && accepts(l) && p.is_some())
+    };
+    let (_, login, password) = matching(Some(host)).or_else(|| matching(None))?;
+    let login = user
+        .map(String::from)
+        .or_else(|| login.clone())
+        .unwrap_or_default();
+    Some((login, password.clone()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETRC: &str = "machine rabbit.example login ops password secret
+machine rabbit.example
+  login app
+  password hunter2
+machine other.example login ops
+default login fallback password anything
+";
+
+    #[test]
+    fn finds_host_of_address() {
+        assert_eq!(host("https://rabbit.example:15671/api"), "rabbit.example");
+        assert_eq!(host("http://user:pw@rabbit.example"), "rabbit.example");
+        assert_eq!(host("http://[::1]:15672"), "::1");
+        assert_eq!(host("rabbit.example:15672"), "rabbit.example");
+    }
+
+    #[test]
+    fn picks_first_login_of_machine() {
+        let found = netrc_lookup(NETRC, "https://rabbit.example:15671", None);
+        assert_eq!(found, Some(("ops".to_string(), "secret".to_string())));
+    }
+
+    #[test]
+    fn picks_entry_of_given_user() {
+        let found = netrc_lookup(NETRC, "https://rabbit.example", Some("app"));
+        assert_eq!(found, Some(("app".to_string(), "hunter2".to_string())));
+    }
+
+    #[test]
+    fn falls_back_to_default() {
+        let found = netrc_lookup(NETRC, "http://other.example", None);
+        assert_eq!(
+            found,
+            Some(("fallback".to_string(), "anything".to_string()))
+        );
+        assert_eq!(
+            netrc_lookup(NETRC, "http://other.example", Some("nobody")),
+            None
+        );
+    }
+
+    #[test]
+    fn first_line_only() {
+        assert_eq!(first_line("pw\nrest\n"), "pw");
+        assert_eq!(first_line(""), "");
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{ManagementClient, Rowable};
+
+use std::{fs, path::Path};
+
+use serde_json::Value;
+
+/// Top level sections of a definitions document
+/// that hold objects, in the order they are imported.
+const SECTIONS: [&str; 10] = [
+    "users",
+    "vhosts",
+    "permissions",
+    "topic_permissions",
+    "parameters",
+    "global_parameters",
+    "policies",
+    "queues",
+    "exchanges",
+    "bindings",
+];
+
+/// A single object found in a definitions document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefinitionItem {
+    pub kind: String,
+    pub vhost: String,
+    pub name: String,
+}
+
+impl DefinitionItem {
+    pub fn headers<'a>() -> [&'a str; 3] {
+        ["Kind", "Vhost", "Name"]
+    }
+}
+
+impl Rowable for DefinitionItem {
+    fn to_row(&self) -> Vec<String> {
+        vec![self.kind.clone(), self.vhost.clone(), self.name.clone()]
+    }
+}
+
+/// What importing a document would do compared to what
+/// the broker currently has.
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    /// Objects that don't exist yet.
+    pub created: Vec<DefinitionItem>,
+    /// Objects that already exist but differ from the
+    /// imported version, so would be overwritten.
+    pub existing: usize,
+}
+
+fn field<'a>(obj: &'a Value, key: &str) -> &'a str {
+    obj.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+/// Builds an item for an object of the given section. Per-vhost
+/// documents leave the vhost out of every object, so `scope`
+/// fills it in.
+fn item(section: &str, obj: &Value, scope: Option<&str>) -> DefinitionItem {
+    let vhost = match obj.get("vhost").and_then(|v| v.as_str()) {
+        Some(v) => v.to_string(),
+        None => scope.unwrap_or("").to_string(),
+    };
+    let (kind, name) = match section {
+        "users" => ("user", field(obj, "name").to_string()),
+        "vhosts" => ("vhost", field(obj, "name").to_string()),
+        "permissions" => ("permission", field(obj, "user").to_string()),
+        "topic_permissions" => (
+            "topic permission",
+            format!("{} on {}", field(obj, "user"), field(obj, "exchange")),
+        ),
+        "parameters" => (
+            "parameter",
+            format!("{}/{}", field(obj, "component"), field(obj, "name")),
+        ),
+        "global_parameters" => ("global parameter", field(obj, "name").to_string()),
+        "policies" => ("policy", field(obj, "name").to_string()),
+        "queues" => ("queue", field(obj, "name").to_string()),
+        "exchanges" => ("exchange", field(obj, "name").to_string()),
+        _ => (
+            "binding",
+            format!(
+                "{} → {} {} [{}]",
+                field(obj, "source"),
+                field(obj, "destination_type"),
+                field(obj, "destination"),
+                field(obj, "routing_key"),
+            ),
+        ),
+    };
+    // Users, vhosts and global parameters are cluster wide.
+    let vhost = match section {
+        "users" | "vhosts" | "global_parameters" => String::new(),
+        _ => vhost,
+    };
+    DefinitionItem {
+        kind: kind.to_string(),
+        vhost,
+        name,
+    }
+}
+
+/// Flattens a definitions document into its objects,
+/// alongside the JSON each was read from.
+fn entries<'a>(definitions: &'a Value, scope: Option<&'a str>) -> Vec<(DefinitionItem, &'a Value)> {
+    SECTIONS
+        .iter()
+        .flat_map(|section| {
+            definitions
+                .get(*section)
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .map(move |obj| (item(section, obj, scope), obj))
+        })
+        .collect()
+}
+
+/// Flattens a definitions document into its objects.
+pub fn items(definitions: &Value, scope: Option<&str>) -> Vec<DefinitionItem> {
+    entries(definitions, scope)
+        .into_iter()
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Compares a document against the broker's current definitions
+/// without changing anything. Objects identical to what the
+/// broker has are left out entirely.
+pub fn plan(incoming: &Value, current: &Value, scope: Option<&str>) -> ImportPlan {
+    let current = entries(current, scope);
+    let mut plan = ImportPlan::default();
+    for (i, obj) in entries(incoming, scope) {
+        match current.iter().find(|(c, _)| *c == i) {
+            Some((_, have)) if *have == obj => {}
+            Some(_) => plan.existing += 1,
+            None => plan.created.push(i),
+        }
+    }
+    plan
+}
+
+/// Reads and parses a definitions file.
+pub fn read(path: &Path) -> Result<Value, String> {
+    let name = path.display();
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", name, e))?;
+    let definitions: Value = serde_json::from_str(&contents)
+        .map_err(|e| format!("{} is not valid JSON: {}", name, e))?;
+    if !definitions.is_object() {
+        return Err(format!("{} is not a definitions document", name));
+    }
+    Ok(definitions)
+}
+
+/// Fetches the definitions and writes them as pretty JSON,
+/// to stdout if no path is given. Returns how many objects
+/// were exported.
+pub fn export<M>(client: &M, vhost: Option<&str>, path: Option<&Path>) -> Result<usize, String>
+where
+    M: ManagementClient,
+{
+    let definitions = client.get_definitions(vhost)?;
+    let pretty = serde_json::to_string_pretty(&definitions).map_err(|e| e.to_string())?;
+    match path {
+        Some(p) => fs::write(p, pretty + "\n")
+            .map_err(|e| format!("Unable to write {}: {}", p.display(), e))?,
+        None => println!("{}", pretty),
+    }
+    Ok(items(&definitions, vhost).len())
+}
+
+/// Works out what importing would create, and imports
+/// unless this is a dry run.
+pub fn import<M>(
+    client: &M,
+    vhost: Option<&str>,
+    definitions: &Value,
+    dry_run: bool,
+) -> Result<ImportPlan, String>
+where
+    M: ManagementClient,
+{
+    let current = client.get_definitions(vhost)?;
+    let plan = plan(definitions, &current, vhost);
+    if !dry_run {
+        client.post_definitions(vhost, definitions)?;
+    }
+    Ok(plan)
+}
+
+/// Prints an import plan as a plain table.
+pub fn print_plan(plan: &ImportPlan, dry_run: bool) {
+    let verb = if dry_run { "Would create" } else { "Created" };
+    if plan.created.is_empty() {
+        println!("{} nothing new.", verb);
+    } else {
+        println!("{} {} objects:", verb, plan.created.len());
+        let kind_w = plan
+            .created
+            .iter()
+            .map(|i| i.kind.len())
+            .max()
+            .unwrap_or(4)
+            .max(4);
+        let vhost_w = plan
+            .created
+            .iter()
+            .map(|i| i.vhost.len())
+            .max()
+            .unwrap_or(5)
+            .max(5);
+        for i in &plan.created {
+            println!(
+                "  {:<kw$}  {:<vw$}  {}",
+                i.kind,
+                i.vhost,
+                i.name,
+                kw = kind_w,
+                vw = vhost_w
+            );
+        }
+    }
+    if plan.existing > 0 {
+        println!(
+            "{} objects already exist and {} updated.",
+            plan.existing,
+            if dry_run { "would be" } else { "were" }
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn plan_skips_identical_definitions() {
+        let current = json!({
+            "queues": [
+                {"name": "same", "vhost": "/", "durable": true},
+                {"name": "changed", "vhost": "/", "durable": true},
+            ]
+        });
+        let incoming = json!({
+            "queues": [
+                {"name": "same", "vhost": "/", "durable": true},
+                {"name": "changed", "vhost": "/", "durable": false},
+                {"name": "new", "vhost": "/", "durable": true},
+            ]
+        });
+        let plan = plan(&incoming, &current, None);
+        assert_eq!(plan.existing, 1);
+        let created: Vec<&str> = plan.created.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(created, ["new"]);
+    }
+}
@@ -0,0 +1,492 @@
+use crate::{
+    topology::{BRANCH, LAST_BRANCH, PIPE, SPACE},
+    ManagementClient,
+};
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use termion::{color, style};
+
+type Attributes = BTreeMap<String, Value>;
+
+/// Kinds of object that are compared, in the order they are printed.
+const KINDS: [&str; 4] = ["exchange", "queue", "binding", "policy"];
+
+/// An object as seen by the diff, whether it came from a
+/// definitions file or the live broker. Nested maps such as
+/// `arguments` are flattened into dotted keys so a single
+/// argument change is reported on its own.
+#[derive(Debug, Clone)]
+struct Object {
+    kind: &'static str,
+    vhost: String,
+    name: String,
+    attrs: Attributes,
+}
+
+impl Object {
+    fn new(kind: &'static str, vhost: &str, name: String) -> Self {
+        Self {
+            kind,
+            vhost: vhost.to_string(),
+            name,
+            attrs: Attributes::new(),
+        }
+    }
+
+    fn attr<V: Into<Value>>(mut self, key: &str, value: V) -> Self {
+        self.attrs.insert(key.to_string(), value.into());
+        self
+    }
+
+    fn nested<'a, I>(mut self, prefix: &str, map: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a String, &'a Value)>,
+    {
+        for (k, v) in map {
+            self.attrs.insert(format!("{}.{}", prefix, k), v.clone());
+        }
+        self
+    }
+
+    /// Sort key grouping objects by vhost, then kind.
+    fn order(&self) -> (String, usize, String) {
+        let rank = KINDS.iter().position(|k| *k == self.kind).unwrap_or(0);
+        (self.vhost.clone(), rank, self.name.clone())
+    }
+}
+
+/// Names a binding after its source, destination and routing key,
+/// which together are all that tell two bindings apart.
+pub fn binding_name(source: &str, dest_type: &str, dest: &str, routing_key: &str) -> String {
+    format!("{} → {} {} [{}]", source, dest_type, dest, routing_key)
+}
+
+/// Whether an exchange is created by the broker itself. These
+/// are never part of exported definitions.
+fn is_builtin_exchange(name: &str) -> bool {
+    name.is_empty() || name.starts_with("amq.")
+}
+
+/// Whether a queue belongs to a connection rather than to the
+/// definitions: exclusive queues and server named `amq.gen-` ones.
+/// Neither is ever exported.
+pub fn is_connection_queue(exclusive: bool, name: &str) -> bool {
+    exclusive || name.starts_with("amq.gen-")
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// Only in the definitions file.
+    Added,
+    /// Only on the broker.
+    Removed,
+    /// On both, with different attributes.
+    Changed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FieldChange {
+    pub key: String,
+    pub live: Option<Value>,
+    pub file: Option<Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Change {
+    pub change: ChangeKind,
+    pub kind: &'static str,
+    pub vhost: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+}
+
+fn str_field<'a>(obj: &'a Value, key: &str) -> &'a str {
+    obj.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn bool_field(obj: &Value, key: &str) -> bool {
+    obj.get(key).and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+fn map_field(obj: &Value, key: &str) -> Attributes {
+    match obj.get(key) {
+        Some(Value::Object(m)) => m.clone().into_iter().collect(),
+        _ => Attributes::new(),
+    }
+}
+
+/// Reads the comparable objects out of a definitions document.
+/// Per-vhost documents have no vhost on their objects, so
+/// `scope` fills it in.
+fn from_definitions(definitions: &Value, scope: Option<&str>) -> Vec<Object> {
+    let section = |name: &str| -> Vec<Value> {
+        definitions
+            .get(name)
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let vhost = |obj: &Value| -> String {
+        match obj.get("vhost").and_then(|v| v.as_str()) {
+            Some(v) => v.to_string(),
+            None => scope.unwrap_or("/").to_string(),
+        }
+    };
+    let mut objects = Vec::new();
+    for q in section("queues") {
+        objects.push(
+            Object::new("queue", &vhost(&q), str_field(&q, "name").to_string())
+                .attr("durable", bool_field(&q, "durable"))
+                .attr("auto_delete", bool_field(&q, "auto_delete"))
+                .nested("arguments", &map_field(&q, "arguments")),
+        );
+    }
+    for e in section("exchanges") {
+        if is_builtin_exchange(str_field(&e, "name")) {
+            continue;
+        }
+        objects.push(
+            Object::new("exchange", &vhost(&e), str_field(&e, "name").to_string())
+                .attr("type", str_field(&e, "type"))
+                .attr("durable", bool_field(&e, "durable"))
+                .attr("auto_delete", bool_field(&e, "auto_delete"))
+                .attr("internal", bool_field(&e, "internal"))
+                .nested("arguments", &map_field(&e, "arguments")),
+        );
+    }
+    for b in section("bindings") {
+        if str_field(&b, "source").is_empty() {
+            continue;
+        }
+        let name = binding_name(
+            str_field(&b, "source"),
+            str_field(&b, "destination_type"),
+            str_field(&b, "destination"),
+            str_field(&b, "routing_key"),
+        );
+        objects.push(
+            Object::new("binding", &vhost(&b), name)
+                .nested("arguments", &map_field(&b, "arguments")),
+        );
+    }
+    for p in section("policies") {
+        objects.push(
+            Object::new("policy", &vhost(&p), str_field(&p, "name").to_string())
+                .attr("pattern", str_field(&p, "pattern"))
+                .attr("apply-to", str_field(&p, "apply-to"))
+                .attr(
+                    "priority",
+                    p.get("priority").and_then(|v| v.as_i64()).unwrap_or(0),
+                )
+                .nested("definition", &map_field(&p, "definition")),
+        );
+    }
+    objects
+}
+
+/// Fetches the same kinds of object from the broker.
+fn from_broker<M>(client: &M) -> Result<Vec<Object>, String>
+where
+    M: ManagementClient,
+{
+    let mut objects = Vec::new();
+    for q in client.get_queues_info()? {
+        if is_connection_queue(q.exclusive, &q.name) {
+            continue;
+        }
+        objects.push(
+            Object::new("queue", &q.vhost, q.name.clone())
+                .attr("durable", q.durable)
+                .attr("auto_delete", q.auto_delete)
+                .nested("arguments", &q.arguments),
+        );
+    }
+    for e in client.get_exchange_overview()? {
+        if is_builtin_exchange(&e.name) {
+            continue;
+        }
+        objects.push(
+            Object::new("exchange", &e.vhost, e.name.clone())
+                .attr("type", e.t.as_str())
+                .attr("durable", e.durable)
+                .attr("auto_delete", e.auto_delete)
+                .attr("internal", e.internal)
+                .nested("arguments", &e.arguments),
+        );
+    }
+    for b in client.get_bindings()? {
+        // Every queue is implicitly bound to the default
+        // exchange, those bindings are never exported.
+        if b.source.is_empty() {
+            continue;
+        }
+        let name = binding_name(&b.source, &b.dest_type, &b.dest, &b.routing_key);
+        objects.push(Object::new("binding", &b.vhost, name).nested("arguments", &b.arguments));
+    }
+    for p in client.get_policies()? {
+        objects.push(
+            Object::new("policy", &p.vhost, p.name.clone())
+                .attr("pattern", p.pattern.as_str())
+                .attr("apply-to", p.apply_to.as_str())
+                .attr("priority", p.priority)
+                .nested("definition", &p.definition),
+        );
+    }
+    Ok(objects)
+}
+
+/// Compares the two sides, matching objects on kind, vhost and
+/// name. Bindings are named after their source, destination and
+/// routing key, so only their arguments can show up as changed.
+fn compare(file: Vec<Object>, live: Vec<Object>) -> Vec<Change> {
+    let key = |o: &Object| (o.order(), o.kind);
+    let file: BTreeMap<_, Object> = file.into_iter().map(|o| (key(&o), o)).collect();
+    let live: BTreeMap<_, Object> = live.into_iter().map(|o| (key(&o), o)).collect();
+    let mut keys: Vec<_> = file.keys().chain(live.keys()).cloned().collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut changes = Vec::new();
+    for k in keys {
+        let (change, obj, fields) = match (file.get(&k), live.get(&k)) {
+            (Some(f), None) => (ChangeKind::Added, f, Vec::new()),
+            (None, Some(l)) => (ChangeKind::Removed, l, Vec::new()),
+            (Some(f), Some(l)) => {
+                let mut attrs: Vec<&String> = f.attrs.keys().chain(l.attrs.keys()).collect();
+                attrs.sort();
+                attrs.dedup();
+                let fields: Vec<FieldChange> = attrs
+                    .into_iter()
+                    .filter(|a| f.attrs.get(*a) != l.attrs.get(*a))
+                    .map(|a| FieldChange {
+                        key: a.clone(),
+                        live: l.attrs.get(a).cloned(),
+                        file: f.attrs.get(a).cloned(),
+                    })
+                    .collect();
+                if fields.is_empty() {
+                    continue;
+                }
+                (ChangeKind::Changed, f, fields)
+            }
+            (None, None) => continue,
+        };
+        changes.push(Change {
+            change,
+            kind: obj.kind,
+            vhost: obj.vhost.clone(),
+            name: obj.name.clone(),
+            fields,
+        });
+    }
+    changes
+}
+
+/// Diffs a definitions document against the live broker,
+/// optionally limited to a single vhost.
+pub fn diff<M>(client: &M, definitions: &Value, vhost: Option<&str>) -> Result<Vec<Change>, String>
+where
+    M: ManagementClient,
+{
+    let in_scope = |o: &Object| match vhost {
+        Some(v) => o.vhost == v,
+        None => true,
+    };
+    let file = from_definitions(definitions, vhost)
+        .into_iter()
+        .filter(in_scope)
+        .collect();
+    let live = from_broker(client)?.into_iter().filter(in_scope).collect();
+    Ok(compare(file, live))
+}
+
+fn fmt_value(v: &Option<Value>) -> String {
+    match v {
+        Some(v) => v.to_string(),
+        None => "(unset)".to_string(),
+    }
+}
+
+/// Prints the changes as a tree of vhost, kind and object,
+/// with changed attributes as leaves.
+pub fn print_tree(changes: &[Change], colored: bool) {
+    let paint = |text: &str, c: &dyn color::Color| {
+        if colored {
+            format!("{}{}{}", color::Fg(c), text, style::Reset)
+        } else {
+            text.to_string()
+        }
+    };
+    let mut vhosts: Vec<&str> = changes.iter().map(|c| c.vhost.as_str()).collect();
+    vhosts.dedup();
+    for vhost in vhosts {
+        println!("vhost {}", vhost);
+        let in_vhost: Vec<&Change> = changes.iter().filter(|c| c.vhost == vhost).collect();
+        let kinds: Vec<&str> = KINDS
+            .iter()
+            .copied()
+            .filter(|k| in_vhost.iter().any(|c| c.kind == *k))
+            .collect();
+        for (ki, kind) in kinds.iter().enumerate() {
+            let last_kind = ki == kinds.len() - 1;
+            let (branch, pipe) = if last_kind {
+                (LAST_BRANCH, SPACE)
+            } else {
+                (BRANCH, PIPE)
+            };
+            println!("{}{}", branch, kind);
+            let of_kind: Vec<&&Change> = in_vhost.iter().filter(|c| c.kind == *kind).collect();
+            for (ci, c) in of_kind.iter().enumerate() {
+                let last = ci == of_kind.len() - 1;
+                let (branch, child_pipe) = if last {
+                    (LAST_BRANCH, SPACE)
+                } else {
+                    (BRANCH, PIPE)
+                };
+                let label = match c.change {
+                    ChangeKind::Added => paint(&format!("+ {}", c.name), &color::Green),
+                    ChangeKind::Removed => paint(&format!("- {}", c.name), &color::Red),
+                    ChangeKind::Changed => paint(&format!("~ {}", c.name), &color::Yellow),
+                };
+                println!("{}{}{}", pipe, branch, label);
+                for (fi, f) in c.fields.iter().enumerate() {
+                    let branch = if fi == c.fields.len() - 1 {
+                        LAST_BRANCH
+                    } else {
+                        BRANCH
+                    };
+                    println!(
+                        "{}{}{}{}: {} → {}",
+                        pipe,
+                        child_pipe,
+                        branch,
+                        f.key,
+                        paint(&fmt_value(&f.live), &color::Red),
+                        paint(&fmt_value(&f.file), &color::Green),
+                    );
+                }
+            }
+        }
+    }
+    println!("{}", summary(changes));
+}
+
+fn count(changes: &[Change], kind: ChangeKind) -> usize {
+    changes.iter().filter(|c| c.change == kind).count()
+}
+
+fn summary(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "No differences.".to_string();
+    }
+    format!(
+        "{} added, {} removed, {} changed.",
+        count(changes, ChangeKind::Added),
+        count(changes, ChangeKind::Removed),
+        count(changes, ChangeKind::Changed)
+    )
+}
+
+/// Prints the changes as JSON for scripts and CI.
+pub fn print_json(changes: &[Change]) {
+    let out = json!({
+        "added": count(changes, ChangeKind::Added),
+        "removed": count(changes, ChangeKind::Removed),
+        "changed": count(changes, ChangeKind::Changed),
+        "changes": changes,
+    });
+    println!("{}", serde_json::to_string_pretty(&out).unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_queues_are_skipped() {
+        assert!(is_connection_queue(true, "orders"));
+        assert!(is_connection_queue(false, "amq.gen-JzTY20BRgKO-HjmUJj0wLg"));
+        assert!(!is_connection_queue(false, "orders"));
+        assert!(!is_connection_queue(false, "amq.orders"));
+    }
+
+    fn queue(vhost: &str, name: &str, durable: bool) -> Object {
+        Object::new("queue", vhost, name.to_string()).attr("durable", durable)
+    }
+
+    #[test]
+    fn reads_definitions() {
+        let defs = json!({
+            "queues": [{"name": "q", "durable": true, "arguments": {"x-max-length": 10}}],
+            "exchanges": [{"name": "amq.topic"}, {"name": "events", "type": "topic"}],
+            "bindings": [
+                {"source": "", "destination": "q", "destination_type": "queue", "routing_key": "q"},
+                {"source": "events", "destination": "q", "destination_type": "queue", "routing_key": "#"}
+            ]
+        });
+        let objects = from_definitions(&defs, Some("shop"));
+        let names: Vec<(&str, &str, &str)> = objects
+            .iter()
+            .map(|o| (o.kind, o.vhost.as_str(), o.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("queue", "shop", "q"),
+                ("exchange", "shop", "events"),
+                ("binding", "shop", "events → queue q [#]"),
+            ]
+        );
+        assert_eq!(objects[0].attrs["arguments.x-max-length"], json!(10));
+    }
+
+    #[test]
+    fn compares_on_kind_vhost_and_name() {
+        let file = vec![
+            queue("/", "a", true),
+            queue("/", "b", true),
+            queue("v", "c", true),
+        ];
+        let live = vec![
+            queue("/", "a", true),
+            queue("/", "b", false),
+            queue("/", "c", true),
+        ];
+        let changes: Vec<(ChangeKind, &str, String)> = compare(file, live)
+            .into_iter()
+            .map(|c| (c.change, c.kind, format!("{}/{}", c.vhost, c.name)))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (ChangeKind::Changed, "queue", "//b".to_string()),
+                (ChangeKind::Removed, "queue", "//c".to_string()),
+                (ChangeKind::Added, "queue", "v/c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_changed_fields() {
+        let file = vec![queue("/", "q", true).attr("arguments.x-max-length", 10)];
+        let live = vec![queue("/", "q", false)];
+        let change = compare(file, live).pop().unwrap();
+        let fields: Vec<(&str, Option<Value>, Option<Value>)> = change
+            .fields
+            .iter()
+            .map(|f| (f.key.as_str(), f.live.clone(), f.file.clone()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("arguments.x-max-length", None, Some(json!(10))),
+                ("durable", Some(json!(false)), Some(json!(true))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{
+    models::{
+        ChannelInfo, ConnectionInfo, ExchangeBindings, ExchangeInfo, NodeInfo, Overview, QueueInfo,
+    },
+    snapshot::{csv_field, tsv_field, Format},
+    ManagementClient, Rowable,
+};
+
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+/// Resources that can be listed with `rabbitui get`.
+pub const RESOURCES: [&str; 7] = [
+    "queues",
+    "exchanges",
+    "bindings",
+    "connections",
+    "channels",
+    "nodes",
+    "overview",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resource {
+    Queues,
+    Exchanges,
+    Bindings,
+    Connections,
+    Channels,
+    Nodes,
+    Overview,
+}
+
+impl FromStr for Resource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queues" => Ok(Resource::Queues),
+            "exchanges" => Ok(Resource::Exchanges),
+            "bindings" => Ok(Resource::Bindings),
+            "connections" => Ok(Resource::Connections),
+            "channels" => Ok(Resource::Channels),
+            "nodes" => Ok(Resource::Nodes),
+            "overview" => Ok(Resource::Overview),
+            _ => Err(format!("unknown resource: {}", s)),
+        }
+    }
+}
+
+/// Columns holding names picked by users, which stay strings in
+/// JSON even when they look like numbers, e.g. a queue named `42`.
+const IDENTIFIERS: [&str; 7] = [
+    "Name",
+    "Vhost",
+    "User",
+    "From",
+    "To",
+    "Routing key",
+    "Policy",
+];
+
+/// The same rows the tabs show, ready to be printed.
+struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new<R: Rowable>(headers: &[&str], data: &[R]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: data.iter().map(|r| r.to_row()).collect(),
+        }
+    }
+
+    /// Same as `new` with the vhost of each row in front, since
+    /// the tabs leave it out for resources scoped to a vhost.
+    fn with_vhost<R: Rowable>(headers: &[&str], data: &[R], vhost: fn(&R) -> &str) -> Self {
+        let mut headers = headers.to_vec();
+        headers.insert(0, "Vhost");
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: data
+                .iter()
+                .map(|r| {
+                    let mut row = r.to_row();
+                    row.insert(0, vhost(r).to_string());
+                    row
+                })
+                .collect(),
+        }
+    }
+
+    fn fetch<M: ManagementClient>(client: &M, resource: Resource) -> Result<Self, String> {
+        Ok(match resource {
+            Resource::Queues => {
+                Table::with_vhost(&QueueInfo::headers(), &client.get_queues_info()?, |q| {
+                    &q.vhost
+                })
+            }
+            Resource::Exchanges => Table::with_vhost(
+                &ExchangeInfo::headers(),
+                &client.get_exchange_overview()?,
+                |e| &e.vhost,
+            ),
+            Resource::Bindings => {
+                let mut headers = vec!["Vhost", "From", "Type"];
+                headers.extend(ExchangeBindings::headers().iter());
+                let rows = client
+                    .get_bindings()?
+                    .iter()
+                    .map(|b| {
+                        let source = if b.source.is_empty() {
+                            "(AMQP DEFAULT)".to_string()
+                        } else {
+                            b.source.clone()
+                        };
+                        let mut row = vec![b.vhost.clone(), source, b.dest_type.clone()];
+                        row.extend(b.to_row());
+                        row
+                    })
+                    .collect();
+                Table {
+                    headers: headers.iter().map(|h| h.to_string()).collect(),
+                    rows,
+                }
+            }
+            Resource::Connections => {
+                Table::new(&ConnectionInfo::headers(), &client.get_connections()?)
+            }
+            Resource::Channels => Table::new(&ChannelInfo::headers(), &client.get_channels()?),
+            Resource::Nodes => Table::new(&NodeInfo::headers(), &client.get_nodes()?),
+            Resource::Overview => Table::new(&Overview::headers(), &[client.get_overview()?]),
+        })
+    }
+
+    fn print(&self, format: Format) {
+        match format {
+            Format::Table => self.print_aligned(),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&self.to_json()).unwrap()),
+            Format::Csv => self.print_delimited(",", csv_field),
+            Format::Tsv => self.print_delimited("\t", tsv_field),
+        }
+    }
+
+    fn print_aligned(&self) {
+        if self.rows.is_empty() {
+            println!("  (none found)");
+            return;
+        }
+        let widths: Vec<usize> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                self.rows
+                    .iter()
+                    .map(|r| r[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(h.len())
+            })
+            .collect();
+        let line = |cells: Vec<String>| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(widths.iter())
+                .map(|(c, w)| format!("{:<w$}", c, w = w))
+                .collect();
+            println!("  {}", padded.join("  ").trim_end());
+        };
+        line(self.headers.iter().map(|h| h.to_uppercase()).collect());
+        line(widths.iter().map(|w| "-".repeat(*w)).collect());
+        for r in &self.rows {
+            line(r.clone());
+        }
+    }
+
+    fn print_delimited(&self, sep: &str, field: fn(&str) -> String) {
+        let keys: Vec<String> = self.headers.iter().map(|h| field(&key(h))).collect();
+        println!("{}", keys.join(sep));
+        for r in &self.rows {
+            let fields: Vec<String> = r.iter().map(|f| field(f)).collect();
+            println!("{}", fields.join(sep));
+        }
+    }
+
+    /// Rows as objects keyed by header, with numeric and boolean
+    /// cells as JSON numbers and booleans. Identifier columns are
+    /// always strings.
+    fn to_json(&self) -> Value {
+        let objects = self
+            .rows
+            .iter()
+            .map(|r| {
+                let mut object = Map::new();
+                for (h, c) in self.headers.iter().zip(r.iter()) {
+                    let parsed = if IDENTIFIERS.contains(&h.as_str()) {
+                        None
+                    } else {
+                        serde_json::from_str::<Value>(c).ok()
+                    };
+                    let value = match parsed {
+                        Some(v) if v.is_number() || v.is_boolean() => v,
+                        _ => Value::from(c.clone()),
+                    };
+                    object.insert(key(h), value);
+                }
+                Value::Object(object)
+            })
+            .collect();
+        Value::Array(objects)
+    }
+}
+
+/// Turns a header like "Deliver / Get" into a field name like `deliver_get`.
+fn key(header: &str) -> String {
+    header
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join("_")
+}
+
+/// Prints every object of a resource in the given format.
+pub fn print<M: ManagementClient>(
+    client: &M,
+    resource: Resource,
+    format: Format,
+) -> Result<(), String> {
+    Table::fetch(client, resource)?.print(format);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(headers: &[&str], row: &[&str]) -> Table {
+        Table {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: vec![row.iter().map(|c| c.to_string()).collect()],
+        }
+    }
+
+    #[test]
+    fn json_keeps_identifiers_as_strings() {
+        let t = table(
+            &["Vhost", "Name", "Ready", "Running"],
+            &["1", "true", "5", "false"],
+        );
+        assert_eq!(
+            t.to_json(),
+            serde_json::json!([{"vhost": "1", "name": "true", "ready": 5, "running": false}])
+        );
+    }
+
+    #[test]
+    fn json_keeps_formatted_cells_as_strings() {
+        let t = table(&["Memory", "Alarms"], &["100 MiB / 1000 MiB", ""]);
+        assert_eq!(
+            t.to_json(),
+            serde_json::json!([{"memory": "100 MiB / 1000 MiB", "alarms": ""}])
+        );
+    }
+
+    #[test]
+    fn keys_from_headers() {
+        assert_eq!(key("Deliver / Get"), "deliver_get");
+        assert_eq!(key("Uptime (s)"), "uptime_s");
+    }
+}
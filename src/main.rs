@@ -1,28 +1,66 @@
+mod apply;
+mod auth;
+mod baseline;
+mod check;
 mod client;
 mod config;
+mod credentials;
+mod definitions;
+mod diff;
 mod events;
+mod get;
+mod metrics;
 mod models;
+mod permissions;
+mod snapshot;
+mod tls;
+mod topology;
 mod views;
 mod widgets;
 
+use apply::Spec;
 use client::Client;
-use config::AppConfig;
+use config::{ConfigFile, Settings};
 use events::{Event, Events};
-use models::{ExchangeBindings, ExchangeInfo, MQMessage, Overview, QueueInfo};
-use views::{exchange::ExchangePane, overview::OverviewPane, queues::QueuesPane, StatefulPane};
+use models::{
+    BindingDeclare, ChannelInfo, ConnectionInfo, ExchangeBindings, ExchangeDeclare, ExchangeInfo,
+    FederationLink, MQMessage, NodeInfo, Overview, Parameter, Permission, Policy, QueueDeclare,
+    QueueInfo, ShovelStatus, TopicPermission, User, UserUpdate, Vhost, VhostLimits,
+};
+use topology::Topology;
+use views::{
+    exchange::ExchangePane,
+    federation::{self, FederationPane},
+    limits::{self, LimitsPane},
+    overview::OverviewPane,
+    policies::{self, PoliciesPane},
+    queues::QueuesPane,
+    shovels::ShovelsPane,
+    topology::TopologyPane,
+    users::{self, UsersPane},
+    StatefulPane,
+};
+use widgets::clusters::ClusterPicker;
 
 use std::{
     collections::HashMap,
     error::Error,
-    fs,
     io,
     io::Stdout,
-    sync::{mpsc, Arc},
+    path::Path,
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
     thread,
     time::Duration,
 };
 
-use clap::{App as CApp, Arg};
+use clap::{App as CApp, Arg, ArgMatches};
+use serde_json::Value;
 use termion::{
     event::Key,
     input::MouseTerminal,
@@ -38,9 +76,6 @@ use tui::{
     Frame, Terminal,
 };
 
-const DEFAULT_USER: &str = "guest";
-const DEFAULT_PASS: &str = "guest";
-const DEFAULT_ADDR: &str = "http://localhost:15672";
 const ASCII: &str = r#"
    ___       __   __   _ ______     _ 
   / _ \___ _/ /  / /  (_)_  __/_ __(_)
@@ -51,18 +86,69 @@ const ASCII: &str = r#"
 
 type TBackend = TermionBackend<AlternateScreen<MouseTerminal<RawTerminal<Stdout>>>>;
 
+/// Indexes of the tabs whose data is only polled while they are
+/// shown, since reading it is costly, needs more than the
+/// management tag or comes from a plugin.
+const TOPOLOGY_TAB: usize = 3;
+const POLICIES_TAB: usize = 4;
+const LIMITS_TAB: usize = 5;
+const USERS_TAB: usize = 6;
+const SHOVELS_TAB: usize = 7;
+const FEDERATION_TAB: usize = 8;
+
 /// data access trait for the RabbitMQ
 /// Management API. Implemented by any
 /// struct used for the app data backend.
 pub trait ManagementClient: Send + Sync {
-    fn get_exchange_overview(&self) -> Vec<ExchangeInfo>;
-    fn get_exchange_bindings(&self, exch: &ExchangeInfo) -> Vec<ExchangeBindings>;
-    fn get_overview(&self) -> Overview;
-    fn get_queues_info(&self) -> Vec<QueueInfo>;
+    fn get_exchange_overview(&self) -> Result<Vec<ExchangeInfo>, String>;
+    fn get_exchange_bindings(&self, exch: &ExchangeInfo) -> Result<Vec<ExchangeBindings>, String>;
+    fn get_queue_bindings(&self, queue: &QueueInfo) -> Result<Vec<ExchangeBindings>, String>;
+    fn get_bindings(&self) -> Result<Vec<ExchangeBindings>, String>;
+    fn get_overview(&self) -> Result<Overview, String>;
+    fn get_queues_info(&self) -> Result<Vec<QueueInfo>, String>;
     fn post_queue_payload(&self, queue_name: String, vhost: &str, payload: String);
     fn pop_queue_item(&self, queue_name: &str, vhost: &str) -> Option<MQMessage>;
     fn ping(&self) -> Result<(), ()>;
     fn purge_queue(&self, queue_name: &str, vhost: &str);
+    fn get_policies(&self) -> Result<Vec<Policy>, String>;
+    fn get_operator_policies(&self) -> Result<Vec<Policy>, String>;
+    fn put_policy(&self, policy: &Policy) -> Result<(), String>;
+    fn delete_policy(&self, vhost: &str, name: &str) -> Result<(), String>;
+    fn put_operator_policy(&self, policy: &Policy) -> Result<(), String>;
+    fn delete_operator_policy(&self, vhost: &str, name: &str) -> Result<(), String>;
+    fn get_users(&self) -> Result<Vec<User>, String>;
+    fn put_user(&self, user: &UserUpdate) -> Result<(), String>;
+    fn delete_user(&self, name: &str) -> Result<(), String>;
+    fn get_permissions(&self) -> Result<Vec<Permission>, String>;
+    fn put_permission(&self, perm: &Permission) -> Result<(), String>;
+    fn delete_permission(&self, vhost: &str, user: &str) -> Result<(), String>;
+    fn get_topic_permissions(&self) -> Result<Vec<TopicPermission>, String>;
+    fn put_topic_permission(&self, perm: &TopicPermission) -> Result<(), String>;
+    fn delete_topic_permission(&self, vhost: &str, user: &str) -> Result<(), String>;
+    fn get_connections(&self) -> Result<Vec<ConnectionInfo>, String>;
+    fn get_connection_counts(&self) -> Result<HashMap<String, u64>, String>;
+    fn get_channels(&self) -> Result<Vec<ChannelInfo>, String>;
+    fn get_nodes(&self) -> Result<Vec<NodeInfo>, String>;
+    fn get_vhost_limits(&self) -> Result<Vec<VhostLimits>, String>;
+    fn put_vhost_limit(&self, vhost: &str, name: &str, value: i64) -> Result<(), String>;
+    fn delete_vhost_limit(&self, vhost: &str, name: &str) -> Result<(), String>;
+    fn get_definitions(&self, vhost: Option<&str>) -> Result<Value, String>;
+    fn post_definitions(&self, vhost: Option<&str>, definitions: &Value) -> Result<(), String>;
+    fn get_vhosts(&self) -> Result<Vec<Vhost>, String>;
+    fn put_vhost(&self, name: &str) -> Result<(), String>;
+    fn delete_vhost(&self, name: &str) -> Result<(), String>;
+    fn declare_exchange(&self, exchange: &ExchangeDeclare) -> Result<(), String>;
+    fn delete_exchange(&self, vhost: &str, name: &str) -> Result<(), String>;
+    fn declare_queue(&self, queue: &QueueDeclare) -> Result<(), String>;
+    fn delete_queue(&self, vhost: &str, name: &str) -> Result<(), String>;
+    fn declare_binding(&self, binding: &BindingDeclare) -> Result<(), String>;
+    fn delete_binding(&self, binding: &ExchangeBindings) -> Result<(), String>;
+    fn get_shovels(&self) -> Result<Vec<ShovelStatus>, String>;
+    fn get_parameters(&self, component: &str) -> Result<Vec<Parameter>, String>;
+    fn put_parameter(&self, param: &Parameter) -> Result<(), String>;
+    fn delete_parameter(&self, component: &str, vhost: &str, name: &str) -> Result<(), String>;
+    fn get_federation_links(&self) -> Result<Vec<FederationLink>, String>;
+    fn health_check(&self, check: &str) -> Result<Option<String>, String>;
 }
 
 pub trait Rowable {
@@ -137,6 +223,9 @@ impl<T> Datatable<T> {
     }
 
     pub fn next(&mut self) {
+        if self.data.entries.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.data.entries.len() - 1 {
@@ -151,6 +240,9 @@ impl<T> Datatable<T> {
     }
 
     pub fn previous(&mut self) {
+        if self.data.entries.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -217,6 +309,11 @@ where
         self.tabs.previous();
     }
 
+    /// Returns a reference to the currently active pane.
+    pub fn pane_ref(&self) -> &(dyn StatefulPane<B> + 'a) {
+        self.panes[self.tabs.index].as_ref()
+    }
+
     /// Returns a mutable reference to the currently active
     /// pane.
     pub fn pane(&mut self) -> &mut Box<dyn StatefulPane<B> + 'a> {
@@ -241,44 +338,135 @@ struct App<'a, B>
 where
     B: Backend,
 {
-    manager: TabsManager<'a, B, 3>,
+    manager: TabsManager<'a, B, 9>,
+    /// Name of the broker shown in the header.
+    cluster: String,
+    /// Whether TLS verification is off, which the header warns about.
+    insecure: bool,
+    /// Tab being shown, read by the data thread.
+    active_tab: Arc<AtomicUsize>,
+    /// Wakes the data thread up, so a tab is filled as soon
+    /// as it is switched to.
+    wake: mpsc::Sender<()>,
 }
 
 impl<'a, B> App<'a, B>
 where
     B: Backend + 'a,
 {
-    pub fn new<M: ManagementClient + 'static>(client: Arc<M>, config: AppConfig) -> Self {
+    /// Starts the data thread and the panes for a broker. The
+    /// thread stops on its next send once the app is dropped,
+    /// which is how switching clusters tears down the old one.
+    pub fn new<M: ManagementClient + 'static>(client: Arc<M>, settings: &Settings) -> Self {
+        let config = settings.app.clone();
         let thread_client = Arc::clone(&client);
+        let active_tab = Arc::new(AtomicUsize::new(0));
+        let thread_tab = Arc::clone(&active_tab);
+        let (wake, wake_rx) = mpsc::channel();
         let (overview_tx, overview_rx) = mpsc::channel();
         let (exchange_tx, exchange_rx) = mpsc::channel();
         let (queue_tx, queue_rx) = mpsc::channel();
+        let (topology_tx, topology_rx) = mpsc::channel();
+        let (policy_tx, policy_rx) = mpsc::channel();
+        let (limit_tx, limit_rx) = mpsc::channel();
+        let (users_tx, users_rx) = mpsc::channel();
+        let (shovel_tx, shovel_rx) = mpsc::channel();
+        let (federation_tx, federation_rx) = mpsc::channel();
         // Create data thread. Responsible for gathering new data points
-        // and sending to existing receivers.
+        // and sending to existing receivers. Panes keep what they last
+        // got when a fetch fails.
         thread::spawn(move || loop {
-            let overview_data = thread_client.get_overview();
-            if overview_tx.send(overview_data).is_err() {
-                break;
+            let tab = thread_tab.load(Ordering::Relaxed);
+            if let Ok(overview_data) = thread_client.get_overview() {
+                if overview_tx.send(overview_data).is_err() {
+                    break;
+                }
             }
             let exchange_data = thread_client.get_exchange_overview();
-            if exchange_tx.send(exchange_data).is_err() {
+            let queue_data = thread_client.get_queues_info();
+            let limit_data = match &queue_data {
+                Ok(queues) if tab == LIMITS_TAB => Some(limits::fetch(&*thread_client, queues)),
+                Err(e) if tab == LIMITS_TAB => Some(Err(e.clone())),
+                _ => None,
+            };
+            if tab == TOPOLOGY_TAB {
+                if let (Ok(exchanges), Ok(queues), Ok(bindings)) =
+                    (&exchange_data, &queue_data, thread_client.get_bindings())
+                {
+                    let topology_data = Topology::new(exchanges.clone(), queues.clone(), bindings);
+                    if topology_tx.send(topology_data).is_err() {
+                        break;
+                    }
+                }
+            }
+            if let Ok(d) = exchange_data {
+                if exchange_tx.send(d).is_err() {
+                    break;
+                }
+            }
+            if let Ok(d) = queue_data {
+                if queue_tx.send(d).is_err() {
+                    break;
+                }
+            }
+            if tab == POLICIES_TAB && policy_tx.send(policies::fetch(&*thread_client)).is_err() {
                 break;
             }
-            let queue_data = thread_client.get_queues_info();
-            if queue_tx.send(queue_data).is_err() {
+            if let Some(d) = limit_data {
+                if limit_tx.send(d).is_err() {
+                    break;
+                }
+            }
+            if tab == USERS_TAB && users_tx.send(users::fetch(&*thread_client)).is_err() {
+                break;
+            }
+            if tab == SHOVELS_TAB && shovel_tx.send(thread_client.get_shovels()).is_err() {
+                break;
+            }
+            if tab == FEDERATION_TAB
+                && federation_tx
+                    .send(federation::fetch(&*thread_client))
+                    .is_err()
+            {
+                break;
+            }
+            let timeout = Duration::from_millis(config.update_rate);
+            if let Err(RecvTimeoutError::Disconnected) = wake_rx.recv_timeout(timeout) {
                 break;
             }
-            thread::sleep(Duration::from_millis(config.update_rate));
         });
         Self {
             manager: TabsManager::new(
-                ["Overview", "Exchanges", "Queues"],
                 [
-                    Box::new(OverviewPane::new(Arc::clone(&client), overview_rx)),
+                    "Overview",
+                    "Exchanges",
+                    "Queues",
+                    "Topology",
+                    "Policies",
+                    "Limits",
+                    "Users",
+                    "Shovels",
+                    "Federation",
+                ],
+                [
+                    Box::new(OverviewPane::<M>::new(Arc::clone(&client), overview_rx)),
                     Box::new(ExchangePane::<M>::new(Arc::clone(&client), exchange_rx)),
                     Box::new(QueuesPane::<'a, M>::new(Arc::clone(&client), queue_rx)),
+                    Box::new(TopologyPane::new(Arc::clone(&client), topology_rx)),
+                    Box::new(PoliciesPane::<'a, M>::new(Arc::clone(&client), policy_rx)),
+                    Box::new(LimitsPane::<'a, M>::new(Arc::clone(&client), limit_rx)),
+                    Box::new(UsersPane::<'a, M>::new(Arc::clone(&client), users_rx)),
+                    Box::new(ShovelsPane::<'a, M>::new(Arc::clone(&client), shovel_rx)),
+                    Box::new(FederationPane::<'a, M>::new(
+                        Arc::clone(&client),
+                        federation_rx,
+                    )),
                 ],
             ),
+            cluster: settings.cluster_name().to_string(),
+            insecure: settings.tls.insecure,
+            active_tab,
+            wake,
         }
     }
 
@@ -317,7 +505,20 @@ where
                 Constraint::Percentage(5),
             ])
             .split(area);
-        let help_t = Text::raw("Press ? for help");
+        let mut cluster = vec![
+            Span::raw("Cluster: "),
+            Span::styled(&self.cluster, Style::default().fg(Color::Green)),
+        ];
+        if self.insecure {
+            cluster.push(Span::styled(
+                " (TLS NOT VERIFIED)",
+                Style::default().fg(Color::Red),
+            ));
+        }
+        let cluster = Paragraph::new(Spans::from(cluster))
+            .alignment(Alignment::Right)
+            .block(Block::default());
+        let help_t = Text::raw("Press ? for help, C to switch cluster");
         let p = Paragraph::new(help_t)
             .alignment(Alignment::Right)
             .block(Block::default());
@@ -326,6 +527,7 @@ where
             .constraints([Constraint::Percentage(50), Constraint::Min(0)])
             .split(chunks[3]);
         f.render_widget(pg_title, chunks[0]);
+        f.render_widget(cluster, meta_chunks[0]);
         f.render_widget(p, meta_chunks[1]);
     }
 
@@ -348,12 +550,18 @@ where
     /// regardless of active view. Any other keys are passed off
     /// to the tab manager.
     fn handle_key(&mut self, key: Key) {
+        if self.is_capturing_input() {
+            self.manager.pane().handle_key(key);
+            return;
+        }
         match key {
             Key::Char('l') => {
                 self.manager.next();
+                self.tab_changed();
             }
             Key::Char('h') => {
                 self.manager.prev();
+                self.tab_changed();
             }
             _ => {
                 self.manager.pane().handle_key(key);
@@ -361,6 +569,19 @@ where
         }
     }
 
+    /// Lets the data thread know which tab to poll for.
+    fn tab_changed(&self) {
+        self.active_tab
+            .store(self.manager.curr(), Ordering::Relaxed);
+        let _ = self.wake.send(());
+    }
+
+    /// Whether the active pane is taking raw text input, in
+    /// which case app wide keys are passed through to it.
+    fn is_capturing_input(&self) -> bool {
+        self.manager.pane_ref().is_capturing_input()
+    }
+
     /// Handles tick updates. Most cases are just passed
     /// to the tab manager to determine if individual panes
     /// need updated.
@@ -369,116 +590,55 @@ where
     }
 }
 
-/// Load baseline delivered counts from a file.
-/// Format: one "queue_name=count" per line.
-fn load_baseline(path: &str) -> HashMap<String, u64> {
-    let mut map = HashMap::new();
-    if let Ok(contents) = fs::read_to_string(path) {
-        for line in contents.lines() {
-            if let Some((name, val)) = line.split_once('=') {
-                if let Ok(n) = val.parse::<u64>() {
-                    map.insert(name.to_string(), n);
-                }
-            }
-        }
+/// Reads the `check` subcommand's limits, failing on values
+/// that aren't numbers.
+fn check_thresholds(matches: &ArgMatches) -> Result<check::Thresholds, String> {
+    fn number<T: FromStr>(value: &str, name: &str) -> Result<T, String> {
+        value
+            .parse::<T>()
+            .map_err(|_| format!("invalid --{}: {}", name, value))
     }
-    map
-}
-
-/// Save current delivered counts as baseline.
-fn save_baseline(path: &str, queues: &[QueueInfo]) {
-    let lines: Vec<String> = queues
-        .iter()
-        .map(|q| format!("{}={}", q.name, q.message_stats.deliver_get))
-        .collect();
-    let _ = fs::write(path, lines.join("\n"));
-}
-
-/// Prints a row for a known queue.
-fn print_queue_row(q: &QueueInfo, name_w: usize, baseline: &HashMap<String, u64>) {
-    let in_rate = q.message_stats.publish_details.rate;
-    let out_rate = q.message_stats.deliver_get_details.rate;
-    let base = baseline.get(&q.name).copied().unwrap_or(0);
-    let delivered = q.message_stats.deliver_get.saturating_sub(base);
-    println!(
-        "  {:<nw$}  {:>5}  {:>6}  {:>5}  {:>9}  {:>4}  {:>8.1}  {:>8.1}",
-        q.name, q.ready, q.unacked, q.total,
-        delivered, q.consumers, in_rate, out_rate,
-        nw = name_w
-    );
-}
-
-/// Prints a placeholder row for a queue not found in RabbitMQ.
-fn print_missing_row(name: &str, name_w: usize) {
-    println!(
-        "  {:<nw$}  {:>5}  {:>6}  {:>5}  {:>9}  {:>4}  {:>8}  {:>8}",
-        name, "-", "-", "-", "-", "-", "-", "-",
-        nw = name_w
-    );
+    let optional = |name: &str| -> Result<Option<u64>, String> {
+        matches.value_of(name).map(|v| number(v, name)).transpose()
+    };
+    let ports = match matches.values_of("port") {
+        Some(ps) => ps.map(|p| number(p, "port")).collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    Ok(check::Thresholds {
+        depth_warning: optional("depth-warning")?,
+        depth_critical: optional("depth-critical")?,
+        consumers_required: matches
+            .values_of("consumers")
+            .map(|cs| cs.map(String::from).collect())
+            .unwrap_or_default(),
+        ports,
+        certificate_days: number(
+            matches.value_of("certificate-days").unwrap(),
+            "certificate-days",
+        )?,
+    })
 }
 
-/// Prints the table header.
-fn print_snapshot_header(name_w: usize) {
-    println!(
-        "  {:<nw$}  {:>5}  {:>6}  {:>5}  {:>9}  {:>4}  {:>8}  {:>8}",
-        "QUEUE", "READY", "UNACKD", "TOTAL", "DELIVERED", "CONS", "IN/s", "OUT/s",
-        nw = name_w
-    );
-    println!(
-        "  {:<nw$}  {:>5}  {:>6}  {:>5}  {:>9}  {:>4}  {:>8}  {:>8}",
-        "-".repeat(name_w), "-----", "------", "-----", "---------", "----", "--------", "--------",
-        nw = name_w
-    );
+/// Builds the API client for a set of settings.
+fn client(settings: &Settings) -> Result<Client, String> {
+    let c = Client::new(&settings.addr, &settings.user, Some(settings.pass.clone()))
+        .with_tls(&settings.tls)?;
+    match &settings.token {
+        Some(source) => c.with_token(source.clone()),
+        None => Ok(c),
+    }
 }
 
-/// Print a one-shot table of queue states to stdout and exit.
-/// If baseline_path is provided, on first run (file missing) saves current
-/// delivered counts and shows 0. On subsequent runs shows delta from baseline.
-fn print_snapshot(client: &Client, filter: Option<&str>, baseline_path: Option<&str>) {
-    let queues = client.get_queues_info();
-
-    // Handle baseline: load or create
-    let baseline = if let Some(path) = baseline_path {
-        let existing = load_baseline(path);
-        if existing.is_empty() {
-            // First call — save current state as baseline
-            save_baseline(path, &queues);
-            // Return current counts so delivered shows as 0
-            queues
-                .iter()
-                .map(|q| (q.name.clone(), q.message_stats.deliver_get))
-                .collect()
-        } else {
-            existing
-        }
-    } else {
-        HashMap::new()
-    };
-
-    if let Some(f) = filter {
-        let names: Vec<&str> = f.split(',').map(|s| s.trim()).collect();
-        let name_w = names.iter().map(|n| n.len()).max().unwrap_or(5).max(5);
-        print_snapshot_header(name_w);
-        for name in &names {
-            if let Some(q) = queues.iter().find(|q| q.name == *name) {
-                print_queue_row(q, name_w, &baseline);
-            } else {
-                print_missing_row(name, name_w);
-            }
-        }
-    } else {
-        if queues.is_empty() {
-            println!("  (no queues found)");
-            return;
-        }
-        let mut sorted: Vec<&QueueInfo> = queues.iter().collect();
-        sorted.sort_by(|a, b| a.name.cmp(&b.name));
-        let name_w = sorted.iter().map(|q| q.name.len()).max().unwrap_or(5).max(5);
-        print_snapshot_header(name_w);
-        for q in &sorted {
-            print_queue_row(q, name_w, &baseline);
-        }
-    }
+/// Connects to a profile picked in the cluster switcher. Unlike
+/// at startup, a broker that doesn't answer is refused so the
+/// current one stays up.
+fn switch_cluster(file: &ConfigFile, name: &str) -> Result<(Client, Settings), String> {
+    let settings = Settings::from_profile(file, name)?;
+    let c = client(&settings)?;
+    c.ping()
+        .map_err(|_| format!("Unable to ping {} at {}", name, settings.addr))?;
+    Ok((c, settings))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -492,17 +652,31 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .takes_value(true)
                 .short('u')
                 .long("user")
-                .required(false)
-                .default_value(DEFAULT_USER),
+                .required(false),
         )
         .arg(
             Arg::new("pass")
-                .about("Password for the API auth")
+                .about("Password for the API auth. Shows up in shell history and ps, prefer --pass-file or --ask-pass")
                 .takes_value(true)
                 .short('p')
                 .long("pass")
+                .required(false),
+        )
+        .arg(
+            Arg::new("pass-file")
+                .about("File whose first line is the password for the API auth")
+                .takes_value(true)
+                .long("pass-file")
+                .required(false)
+                .conflicts_with("pass"),
+        )
+        .arg(
+            Arg::new("ask-pass")
+                .about("Prompt for the password for the API auth")
+                .long("ask-pass")
                 .required(false)
-                .default_value(DEFAULT_PASS),
+                .takes_value(false)
+                .conflicts_with_all(&["pass", "pass-file"]),
         )
         .arg(
             Arg::new("addr")
@@ -510,8 +684,98 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .takes_value(true)
                 .short('a')
                 .long("addr")
+                .required(false),
+        )
+        .arg(
+            Arg::new("ca-cert")
+                .about("PEM file with the CAs to trust for an https address")
+                .takes_value(true)
+                .long("ca-cert")
+                .required(false),
+        )
+        .arg(
+            Arg::new("client-cert")
+                .about("PEM client certificate for mutual TLS")
+                .takes_value(true)
+                .long("client-cert")
                 .required(false)
-                .default_value(DEFAULT_ADDR),
+                .requires("client-key"),
+        )
+        .arg(
+            Arg::new("client-key")
+                .about("PEM private key of the client certificate")
+                .takes_value(true)
+                .long("client-key")
+                .required(false)
+                .requires("client-cert"),
+        )
+        .arg(
+            Arg::new("client-pkcs12")
+                .about("PKCS#12 archive with the client certificate and key. Its password is read from RABBITUI_PKCS12_PASSWORD")
+                .takes_value(true)
+                .long("client-pkcs12")
+                .required(false)
+                .conflicts_with("client-cert"),
+        )
+        .arg(
+            Arg::new("server-name")
+                .about("Host name to send in SNI and verify the certificate against, instead of the one in the address")
+                .takes_value(true)
+                .long("server-name")
+                .required(false),
+        )
+        .arg(
+            Arg::new("insecure")
+                .about("Skip TLS certificate and hostname verification")
+                .long("insecure")
+                .short('k')
+                .required(false)
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("token-file")
+                .about("File holding a bearer token to use instead of the user and password")
+                .takes_value(true)
+                .long("token-file")
+                .required(false),
+        )
+        .arg(
+            Arg::new("token-endpoint")
+                .about("OAuth 2 token endpoint to get bearer tokens from with the client credentials grant. The secret is read from RABBITUI_CLIENT_SECRET")
+                .takes_value(true)
+                .long("token-endpoint")
+                .required(false)
+                .requires("client-id")
+                .conflicts_with("token-file"),
+        )
+        .arg(
+            Arg::new("client-id")
+                .about("OAuth 2 client id for --token-endpoint")
+                .takes_value(true)
+                .long("client-id")
+                .required(false),
+        )
+        .arg(
+            Arg::new("profile")
+                .about("Named broker profile from the config file")
+                .takes_value(true)
+                .short('P')
+                .long("profile")
+                .required(false),
+        )
+        .arg(
+            Arg::new("config")
+                .about("Config file to read profiles from. Defaults to ~/.config/rabbitui/config.toml")
+                .takes_value(true)
+                .long("config")
+                .required(false),
+        )
+        .arg(
+            Arg::new("update-rate")
+                .about("Milliseconds between refreshes of the TUI")
+                .takes_value(true)
+                .long("update-rate")
+                .required(false),
         )
         .arg(
             Arg::new("snapshot")
@@ -523,43 +787,400 @@ fn main() -> Result<(), Box<dyn Error>> {
         )
         .arg(
             Arg::new("filter")
-                .about("Comma-separated queue names to show in snapshot mode")
+                .about("Comma-separated queue names or globs such as orders.* to show in snapshot mode")
                 .long("filter")
                 .short('f')
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("output")
+                .about("Output format of snapshot mode")
+                .long("output")
+                .short('o')
+                .required(false)
+                .takes_value(true)
+                .possible_values(&["table", "json", "csv", "tsv"])
+                .default_value("table"),
+        )
+        .arg(
+            Arg::new("regex")
+                .about("Only show queues whose name matches this regex in snapshot mode")
+                .long("regex")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("vhost")
+                .about("Only show queues of this vhost in snapshot mode")
+                .long("vhost")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("sort")
+                .about("Sort snapshot rows by name, ready, unacked, total, delivered, consumers, rate or out-rate. Append :asc or :desc to pick the direction")
+                .long("sort")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("top")
+                .about("Only show the first N snapshot rows, after sorting")
+                .long("top")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("watch")
+                .about("Reprint the snapshot every this many seconds, with messages in and out since the last one")
+                .long("watch")
+                .short('w')
+                .required(false)
+                .takes_value(true),
+        )
         .arg(
             Arg::new("baseline")
-                .about("File to store baseline delivered counts. DELIVERED column shows delta from baseline. File is created on first call.")
+                .about("File to store baseline message counts. DELIVERED column shows delta from baseline, with average rates since it was taken. File is created on first call.")
                 .long("baseline")
                 .short('b')
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("baseline-reset")
+                .about("Take a new baseline, overwriting the baseline file")
+                .long("baseline-reset")
+                .required(false)
+                .takes_value(false)
+                .requires("baseline"),
+        )
+        .subcommand(
+            CApp::new("export-definitions")
+                .about("Write the broker definitions to a file as pretty JSON")
+                .arg(
+                    Arg::new("vhost")
+                        .about("Only export definitions of this vhost")
+                        .long("vhost")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("file")
+                        .about("File to write. Prints to stdout if omitted")
+                        .long("file")
+                        .short('f')
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            CApp::new("import-definitions")
+                .about("Load definitions from a JSON file into the broker")
+                .arg(
+                    Arg::new("vhost")
+                        .about("Import into this vhost only")
+                        .long("vhost")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("file")
+                        .about("Definitions file to import")
+                        .long("file")
+                        .short('f')
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .about("List what would be created without changing anything")
+                        .long("dry-run")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            CApp::new("diff")
+                .about("Show how a definitions file differs from the live broker. Exits with 1 if there are differences")
+                .arg(
+                    Arg::new("file")
+                        .about("Definitions file to compare")
+                        .long("file")
+                        .short('f')
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("vhost")
+                        .about("Only compare this vhost")
+                        .long("vhost")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("json")
+                        .about("Print the differences as JSON")
+                        .long("json")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            CApp::new("apply")
+                .about("Converge the broker to the vhosts, exchanges, queues, bindings and policies in a TOML spec")
+                .arg(
+                    Arg::new("file")
+                        .about("Topology spec to apply")
+                        .long("file")
+                        .short('f')
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("prune")
+                        .about("Delete objects in the spec's vhosts that the spec doesn't list")
+                        .long("prune")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .about("Print the plan without changing anything")
+                        .long("dry-run")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            CApp::new("get")
+                .about("Print queues, exchanges, bindings, connections, channels, nodes or the overview and exit")
+                .arg(
+                    Arg::new("resource")
+                        .about("What to list")
+                        .required(true)
+                        .possible_values(&get::RESOURCES),
+                )
+                .arg(
+                    Arg::new("output")
+                        .about("Output format")
+                        .long("output")
+                        .short('o')
+                        .takes_value(true)
+                        .possible_values(&["table", "json", "csv", "tsv"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            CApp::new("serve-metrics")
+                .about("Serve queue, exchange, node and overview metrics in the Prometheus text format")
+                .arg(
+                    Arg::new("listen")
+                        .about("Address to listen on")
+                        .long("listen")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:9419"),
+                ),
+        )
+        .subcommand(
+            CApp::new("check")
+                .about("Run broker health checks and thresholds with Nagios compatible output and exit codes")
+                .arg(
+                    Arg::new("depth-warning")
+                        .about("Warn when a queue holds more messages than this")
+                        .long("depth-warning")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("depth-critical")
+                        .about("Go critical when a queue holds more messages than this")
+                        .long("depth-critical")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("consumers")
+                        .about("Queue, as name or vhost/name, that must have a consumer. Can be repeated")
+                        .long("consumers")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::new("port")
+                        .about("Port a listener must be bound to. Can be repeated")
+                        .long("port")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::new("certificate-days")
+                        .about("Go critical when a TLS certificate expires within this many days")
+                        .long("certificate-days")
+                        .takes_value(true)
+                        .default_value("30"),
+                ),
+        )
         .get_matches();
 
-    let user = matches.value_of("user").unwrap();
-    let pass = matches.value_of("pass").unwrap();
-    let addr = matches.value_of("addr").unwrap();
-    let c = Client::new(addr, user, Some(pass.to_string()));
+    let res = ConfigFile::load(&matches)
+        .and_then(|file| Settings::resolve(&matches, &file).map(|s| (file, s)));
+    let (file, mut settings) = match res {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    if settings.tls.insecure {
+        eprintln!("Warning: TLS certificates are not verified, the connection can be intercepted");
+    }
+    let addr = settings.addr.as_str();
+    let c = match client(&settings) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let default_vhost = settings.vhost.as_deref();
     if let Err(_) = c.ping() {
-        println!("Unable to ping RabbitMQ API.");
-        println!("Check that the service is running and that creds are correct.");
-        return Ok(());
+        match matches.subcommand() {
+            Some(("check", _)) => process::exit(check::unreachable(addr).exit_code()),
+            // The exporter reports the broker as down until it comes up.
+            Some(("serve-metrics", _)) => {}
+            _ => {
+                println!("Unable to ping RabbitMQ API.");
+                println!("Check that the service is running and that creds are correct.");
+                return Ok(());
+            }
+        }
     }
 
-    // Snapshot mode: print table and exit without TUI setup
+    match matches.subcommand() {
+        Some(("export-definitions", sub)) => {
+            let file = sub.value_of("file").map(Path::new);
+            match definitions::export(&c, sub.value_of("vhost").or(default_vhost), file) {
+                Ok(n) => {
+                    if let Some(f) = file {
+                        println!("Exported {} objects to {}", n, f.display());
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Some(("import-definitions", sub)) => {
+            let dry_run = sub.is_present("dry-run");
+            let vhost = sub.value_of("vhost").or(default_vhost);
+            let res = definitions::read(Path::new(sub.value_of("file").unwrap()))
+                .and_then(|defs| definitions::import(&c, vhost, &defs, dry_run));
+            match res {
+                Ok(plan) => definitions::print_plan(&plan, dry_run),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Some(("diff", sub)) => {
+            let res = definitions::read(Path::new(sub.value_of("file").unwrap()))
+                .and_then(|defs| diff::diff(&c, &defs, sub.value_of("vhost").or(default_vhost)));
+            let changes = match res {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(2);
+                }
+            };
+            if sub.is_present("json") {
+                diff::print_json(&changes);
+            } else {
+                diff::print_tree(&changes, termion::is_tty(&io::stdout()));
+            }
+            if !changes.is_empty() {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(("apply", sub)) => {
+            let res = Spec::read(Path::new(sub.value_of("file").unwrap())).and_then(|spec| {
+                let steps = apply::plan(&c, &spec, sub.is_present("prune"))?;
+                apply::apply(&c, &steps, sub.is_present("dry-run"))
+            });
+            if let Err(e) = res {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(("get", sub)) => {
+            let resource = sub.value_of("resource").unwrap().parse().unwrap();
+            let format = sub.value_of("output").unwrap().parse().unwrap();
+            if let Err(e) = get::print(&c, resource, format) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(("serve-metrics", sub)) => {
+            if let Err(e) = metrics::serve(&c, sub.value_of("listen").unwrap()) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(("check", sub)) => {
+            let thresholds = match check_thresholds(sub) {
+                Ok(t) => t,
+                Err(e) => {
+                    println!("RABBITMQ UNKNOWN - {}", e);
+                    process::exit(check::Status::Unknown.exit_code());
+                }
+            };
+            let results = check::run(&c, &thresholds);
+            process::exit(check::report(&results).exit_code());
+        }
+        _ => {}
+    }
+
+    // Snapshot mode: print queue states and exit without TUI setup
     if matches.is_present("snapshot") {
-        let filter = matches.value_of("filter");
-        let baseline = matches.value_of("baseline");
-        print_snapshot(&c, filter, baseline);
+        let selection = snapshot::Selection::new(
+            matches.value_of("filter"),
+            matches.value_of("regex"),
+            matches.value_of("vhost").or(default_vhost),
+            matches.value_of("sort"),
+            matches.value_of("top"),
+        );
+        let selection = match selection {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        let baseline = matches.value_of("baseline").map(Path::new);
+        let reset = matches.is_present("baseline-reset");
+        let format = matches.value_of("output").unwrap().parse().unwrap();
+        match matches.value_of("watch").map(|w| w.parse::<u64>()) {
+            Some(Ok(secs)) if secs > 0 => snapshot::watch(
+                &c,
+                &selection,
+                baseline,
+                reset,
+                format,
+                Duration::from_secs(secs),
+            ),
+            Some(_) => {
+                eprintln!("--watch takes a whole number of seconds above zero");
+                process::exit(1);
+            }
+            None => {
+                if let Err(e) = snapshot::print(&c, &selection, baseline, reset, format) {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
         return Ok(());
     }
 
-    // TODO allow override this.
-    let config = AppConfig::default();
-    let mut app = App::<TBackend>::new::<Client>(Arc::new(c), config);
+    let mut app = App::<TBackend>::new::<Client>(Arc::new(c), &settings);
     // TODO support different backend for non-MacOs.
     // Just need to swap out Termion based upon some config or compile setting.
     let stdout = io::stdout().into_raw_mode()?;
@@ -567,16 +1188,49 @@ fn main() -> Result<(), Box<dyn Error>> {
     let stdout = AlternateScreen::from(stdout);
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    let events = Events::new();
+    let mut events = Events::new();
+    // Quitting is decided below, since panes may be
+    // taking text input that includes the exit key.
+    events.disable_exit_key();
+
+    let mut picker: Option<ClusterPicker> = None;
 
     loop {
-        terminal.draw(|f| app.draw(f))?;
+        terminal.draw(|f| {
+            app.draw(f);
+            if let Some(p) = picker.as_mut() {
+                p.draw(f, f.size());
+            }
+        })?;
 
         match events.next()? {
+            Event::Input(key) if picker.is_some() => {
+                let mut p = picker.take().unwrap();
+                match key {
+                    Key::Esc | Key::Char('q') => continue,
+                    Key::Char('j') => p.next(),
+                    Key::Char('k') => p.previous(),
+                    Key::Char('\n') => match p.selected().map(|name| switch_cluster(&file, name)) {
+                        Some(Ok((c, s))) => {
+                            app = App::new::<Client>(Arc::new(c), &s);
+                            settings = s;
+                            continue;
+                        }
+                        Some(Err(e)) => p.set_error(e),
+                        None => {}
+                    },
+                    _ => {}
+                }
+                picker = Some(p);
+            }
             Event::Input(key) => match key {
-                Key::Char('q') => {
+                Key::Char('q') if !app.is_capturing_input() => {
                     break;
                 }
+                Key::Char('C') if !app.is_capturing_input() => {
+                    let profiles = file.profiles.keys().cloned().collect();
+                    picker = Some(ClusterPicker::new(profiles, settings.profile.as_deref()));
+                }
                 _ => {
                     app.handle_key(key);
                 }
@@ -0,0 +1,311 @@
+use crate::{
+    models::{ExchangeInfo, NodeInfo, QueueInfo},
+    ManagementClient,
+};
+
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+/// Escapes a label value as the Prometheus text format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders `name="value"` pairs into a label set.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let inner: Vec<String> = pairs
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    format!("{{{}}}", inner.join(","))
+}
+
+/// Builds the text exposition, one metric family at a time.
+#[derive(Default)]
+struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    fn family<I>(&mut self, name: &str, kind: &str, help: &str, samples: I)
+    where
+        I: IntoIterator<Item = (String, f64)>,
+    {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(self.out, "{}{} {}", name, labels, value);
+        }
+    }
+
+    fn single(&mut self, name: &str, kind: &str, help: &str, value: f64) {
+        self.family(name, kind, help, vec![(String::new(), value)]);
+    }
+}
+
+fn flag(b: bool) -> f64 {
+    if b {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Polls the management API and renders every metric. When the
+/// broker can't be reached, or any of the fetches fails, only
+/// `rabbitmq_up` is reported rather than a partial scrape.
+pub fn render<M: ManagementClient>(client: &M) -> String {
+    let broker = match client.ping() {
+        Ok(_) => broker_metrics(client),
+        Err(_) => Err("management API unreachable".to_string()),
+    };
+    if let Err(err) = &broker {
+        eprintln!("scrape failed: {}", err);
+    }
+    let mut e = Exposition::default();
+    e.single(
+        "rabbitmq_up",
+        "gauge",
+        "Whether the management API could be reached and scraped.",
+        flag(broker.is_ok()),
+    );
+    if let Ok(out) = broker {
+        e.out.push_str(&out);
+    }
+    e.out
+}
+
+fn broker_metrics<M: ManagementClient>(client: &M) -> Result<String, String> {
+    let mut e = Exposition::default();
+    let overview = client.get_overview()?;
+    let totals = &overview.queue_totals;
+    e.single(
+        "rabbitmq_messages",
+        "gauge",
+        "Messages in all queues.",
+        totals.messages,
+    );
+    e.single(
+        "rabbitmq_messages_ready",
+        "gauge",
+        "Messages ready for delivery in all queues.",
+        totals.messages_ready,
+    );
+    e.single(
+        "rabbitmq_messages_unacked",
+        "gauge",
+        "Messages delivered but not yet acknowledged in all queues.",
+        totals.messages_unacked,
+    );
+    e.single(
+        "rabbitmq_disk_reads_total",
+        "counter",
+        "Messages read from disk.",
+        overview.message_stats.disk_reads,
+    );
+    e.single(
+        "rabbitmq_disk_writes_total",
+        "counter",
+        "Messages written to disk.",
+        overview.message_stats.disk_writes,
+    );
+
+    let queues = client.get_queues_info()?;
+    let per_queue = |value: fn(&QueueInfo) -> u64| {
+        queues.iter().map(move |q| {
+            let l = labels(&[("vhost", &q.vhost), ("queue", &q.name)]);
+            (l, value(q) as f64)
+        })
+    };
+    e.family(
+        "rabbitmq_queue_messages",
+        "gauge",
+        "Messages in the queue.",
+        per_queue(|q| q.total),
+    );
+    e.family(
+        "rabbitmq_queue_messages_ready",
+        "gauge",
+        "Messages ready for delivery in the queue.",
+        per_queue(|q| q.ready),
+    );
+    e.family(
+        "rabbitmq_queue_messages_unacked",
+        "gauge",
+        "Messages delivered from the queue but not yet acknowledged.",
+        per_queue(|q| q.unacked),
+    );
+    e.family(
+        "rabbitmq_queue_consumers",
+        "gauge",
+        "Consumers of the queue.",
+        per_queue(|q| q.consumers),
+    );
+    e.family(
+        "rabbitmq_queue_messages_published_total",
+        "counter",
+        "Messages published to the queue.",
+        per_queue(|q| q.message_stats.publish),
+    );
+    e.family(
+        "rabbitmq_queue_messages_delivered_total",
+        "counter",
+        "Messages delivered or fetched from the queue.",
+        per_queue(|q| q.message_stats.deliver_get),
+    );
+    e.family(
+        "rabbitmq_queue_messages_acked_total",
+        "counter",
+        "Messages acknowledged by consumers of the queue.",
+        per_queue(|q| q.message_stats.ack),
+    );
+
+    let exchanges = client.get_exchange_overview()?;
+    let exchange_labels = |x: &ExchangeInfo| labels(&[("vhost", &x.vhost), ("exchange", &x.name)]);
+    e.family(
+        "rabbitmq_exchange_messages_published_in_total",
+        "counter",
+        "Messages published into the exchange.",
+        exchanges
+            .iter()
+            .map(|x| (exchange_labels(x), x.message_stats.publish_in as f64)),
+    );
+    e.family(
+        "rabbitmq_exchange_messages_published_out_total",
+        "counter",
+        "Messages routed out of the exchange.",
+        exchanges
+            .iter()
+            .map(|x| (exchange_labels(x), x.message_stats.publish_out as f64)),
+    );
+
+    let nodes = client.get_nodes()?;
+    let per_node = |value: fn(&NodeInfo) -> f64| {
+        nodes
+            .iter()
+            .map(move |n| (labels(&[("node", &n.name)]), value(n)))
+    };
+    e.family(
+        "rabbitmq_node_running",
+        "gauge",
+        "Whether the node is running.",
+        per_node(|n| flag(n.running)),
+    );
+    e.family(
+        "rabbitmq_node_uptime_seconds",
+        "gauge",
+        "Time since the node started.",
+        per_node(|n| n.uptime as f64 / 1000.0),
+    );
+    e.family(
+        "rabbitmq_node_mem_used_bytes",
+        "gauge",
+        "Memory used by the node.",
+        per_node(|n| n.mem_used as f64),
+    );
+    e.family(
+        "rabbitmq_node_mem_limit_bytes",
+        "gauge",
+        "Memory high watermark of the node.",
+        per_node(|n| n.mem_limit as f64),
+    );
+    e.family(
+        "rabbitmq_node_mem_alarm",
+        "gauge",
+        "Whether the memory alarm is raised on the node.",
+        per_node(|n| flag(n.mem_alarm)),
+    );
+    e.family(
+        "rabbitmq_node_disk_free_bytes",
+        "gauge",
+        "Free disk space on the node.",
+        per_node(|n| n.disk_free as f64),
+    );
+    e.family(
+        "rabbitmq_node_disk_free_alarm",
+        "gauge",
+        "Whether the disk free alarm is raised on the node.",
+        per_node(|n| flag(n.disk_free_alarm)),
+    );
+    e.family(
+        "rabbitmq_node_fd_used",
+        "gauge",
+        "File descriptors used by the node.",
+        per_node(|n| n.fd_used as f64),
+    );
+    e.family(
+        "rabbitmq_node_fd_total",
+        "gauge",
+        "File descriptors available to the node.",
+        per_node(|n| n.fd_total as f64),
+    );
+    Ok(e.out)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+}
+
+fn handle<M: ManagementClient>(client: &M, mut stream: TcpStream) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let mut reader = match stream.try_clone() {
+        Ok(s) => BufReader::new(s),
+        Err(_) => return,
+    };
+    let mut request = String::new();
+    if reader.read_line(&mut request).is_err() {
+        return;
+    }
+    // Headers are read and ignored so the client isn't reset.
+    let mut header = String::new();
+    while let Ok(n) = reader.read_line(&mut header) {
+        if n == 0 || header.trim().is_empty() {
+            break;
+        }
+        header.clear();
+    }
+    let mut parts = request.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => respond(
+            &mut stream,
+            "200 OK",
+            "text/plain; version=0.0.4",
+            &render(client),
+        ),
+        (Some("GET"), Some("/")) => respond(
+            &mut stream,
+            "200 OK",
+            "text/plain",
+            "rabbitui metrics exporter, scrape /metrics\n",
+        ),
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "not found\n"),
+    }
+}
+
+/// Serves metrics on `listen` until the process is stopped. The
+/// broker is polled on every scrape, one scrape at a time.
+pub fn serve<M: ManagementClient>(client: &M, listen: &str) -> Result<(), String> {
+    let listener = TcpListener::bind(listen).map_err(|e| format!("{}: {}", listen, e))?;
+    println!("Serving metrics on http://{}/metrics", listen);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(s) => handle(client, s),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::models::{Permission, User};
+
+use std::{fmt, str::FromStr};
+
+use regex::Regex;
+
+/// The three kinds of access RabbitMQ checks against a
+/// user's vhost permissions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Configure,
+    Write,
+    Read,
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "configure" | "c" => Ok(Operation::Configure),
+            "write" | "w" => Ok(Operation::Write),
+            "read" | "r" => Ok(Operation::Read),
+            _ => Err(format!(
+                "Unknown operation {}, expected configure, write or read",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Operation::Configure => "configure",
+            Operation::Write => "write",
+            Operation::Read => "read",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Outcome of a permission check, with the reasoning
+/// spelled out one step per line.
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub allowed: bool,
+    pub explanation: Vec<String>,
+}
+
+/// Evaluates whether `user` may perform `op` on `resource` in
+/// `vhost` using the permissions already fetched from the broker.
+/// Mirrors the internal auth backend: the regex is searched for
+/// anywhere in the name (it is not anchored), an empty regex
+/// grants nothing and the default exchange is checked under the
+/// name `amq.default`.
+pub fn check(
+    users: &[User],
+    permissions: &[Permission],
+    user: &str,
+    vhost: &str,
+    op: Operation,
+    resource: &str,
+) -> Verdict {
+    let mut explanation = Vec::new();
+    let deny = |mut explanation: Vec<String>, reason: String| {
+        explanation.push(reason);
+        Verdict {
+            allowed: false,
+            explanation,
+        }
+    };
+
+    if !users.iter().any(|u| u.name == user) {
+        return deny(explanation, format!("User {} does not exist.", user));
+    }
+    let perm = match permissions
+        .iter()
+        .find(|p| p.user == user && p.vhost == vhost)
+    {
+        Some(p) => p,
+        None => {
+            return deny(
+                explanation,
+                format!(
+                    "User {} has no permissions on vhost {}, so it cannot even connect.",
+                    user, vhost
+                ),
+            )
+        }
+    };
+
+    let name = if resource.is_empty() {
+        explanation.push("The default exchange is checked as amq.default.".to_string());
+        "amq.default"
+    } else {
+        resource
+    };
+    let pattern = match op {
+        Operation::Configure => &perm.configure,
+        Operation::Write => &perm.write,
+        Operation::Read => &perm.read,
+    };
+    explanation.push(format!(
+        "{} permission of {} on vhost {} is \"{}\".",
+        op, user, vhost, pattern
+    ));
+    if pattern.is_empty() {
+        return deny(
+            explanation,
+            format!("An empty regex grants no {} access at all.", op),
+        );
+    }
+
+    let re = match Regex::new(pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            return deny(
+                explanation,
+                format!("The regex could not be evaluated locally: {}", e),
+            )
+        }
+    };
+    let allowed = match re.find(name) {
+        Some(m) => {
+            explanation.push(format!(
+                "It matches \"{}\" at {}..{} of {}.",
+                m.as_str(),
+                m.start(),
+                m.end(),
+                name
+            ));
+            if m.start() != 0 || m.end() != name.len() {
+                explanation.push(
+                    "The regex is not anchored, so a partial match is enough. \
+                     Wrap it in ^...$ to match whole names only."
+                        .to_string(),
+                );
+            }
+            true
+        }
+        None => {
+            explanation.push(format!("It does not match {} anywhere.", name));
+            false
+        }
+    };
+    Verdict {
+        allowed,
+        explanation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users() -> Vec<User> {
+        vec![User {
+            name: "app".to_string(),
+            tags: Vec::new(),
+            password_hash: String::new(),
+            hashing_algorithm: None,
+        }]
+    }
+
+    fn permissions(configure: &str, write: &str, read: &str) -> Vec<Permission> {
+        vec![Permission {
+            user: "app".to_string(),
+            vhost: "/".to_string(),
+            configure: configure.to_string(),
+            write: write.to_string(),
+            read: read.to_string(),
+        }]
+    }
+
+    #[test]
+    fn parses_operations() {
+        assert_eq!("W".parse::<Operation>(), Ok(Operation::Write));
+        assert_eq!("configure".parse::<Operation>(), Ok(Operation::Configure));
+        assert!("delete".parse::<Operation>().is_err());
+    }
+
+    #[test]
+    fn unknown_user_and_vhost_are_denied() {
+        let perms = permissions(".*", ".*", ".*");
+        assert!(!check(&users(), &perms, "nobody", "/", Operation::Read, "q").allowed);
+        assert!(!check(&users(), &perms, "app", "other", Operation::Read, "q").allowed);
+    }
+
+    #[test]
+    fn regex_is_not_anchored() {
+        let perms = permissions("", "^orders$", "orders");
+        assert!(check(&users(), &perms, "app", "/", Operation::Read, "my.orders.q").allowed);
+        assert!(!check(&users(), &perms, "app", "/", Operation::Write, "my.orders").allowed);
+        assert!(check(&users(), &perms, "app", "/", Operation::Write, "orders").allowed);
+    }
+
+    #[test]
+    fn empty_regex_grants_nothing() {
+        let perms = permissions("", ".*", ".*");
+        assert!(!check(&users(), &perms, "app", "/", Operation::Configure, "q").allowed);
+    }
+
+    #[test]
+    fn default_exchange_is_amq_default() {
+        let perms = permissions("", "^amq\\.default$", "");
+        assert!(check(&users(), &perms, "app", "/", Operation::Write, "").allowed);
+    }
+
+    #[test]
+    fn invalid_regex_is_denied() {
+        let perms = permissions("", "(", "");
+        assert!(!check(&users(), &perms, "app", "/", Operation::Write, "q").allowed);
+    }
+}
@@ -0,0 +1,681 @@
+use crate::{
+    baseline::{format_age, Baseline},
+    models::QueueInfo,
+    ManagementClient,
+};
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use regex::Regex;
+use serde::Serialize;
+use termion::{clear, cursor};
+
+/// Published and delivered counts per vhost and queue,
+/// kept between ticks of watch mode.
+type Counters = HashMap<(String, String), (u64, u64)>;
+
+/// Ways the snapshot can be printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Columns the snapshot can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Ready,
+    Unacked,
+    Total,
+    Delivered,
+    Consumers,
+    Rate,
+    OutRate,
+}
+
+/// A sort column and direction, written as `key`, `key:asc` or
+/// `key:desc`. Numbers sort descending unless asked otherwise,
+/// so the busiest queues come first, and names ascending.
+#[derive(Debug, Clone, Copy)]
+pub struct Sort {
+    key: SortKey,
+    descending: bool,
+}
+
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, direction) = match s.split_once(':') {
+            Some((k, d)) => (k, Some(d)),
+            None => (s, None),
+        };
+        let key = match key {
+            "name" => SortKey::Name,
+            "ready" => SortKey::Ready,
+            "unacked" => SortKey::Unacked,
+            "total" => SortKey::Total,
+            "delivered" => SortKey::Delivered,
+            "consumers" => SortKey::Consumers,
+            "rate" => SortKey::Rate,
+            "out-rate" => SortKey::OutRate,
+            _ => return Err(format!("unknown sort key: {}", key)),
+        };
+        let descending = match direction {
+            None => key != SortKey::Name,
+            Some("asc") => false,
+            Some("desc") => true,
+            Some(d) => return Err(format!("unknown sort direction: {}", d)),
+        };
+        Ok(Self { key, descending })
+    }
+}
+
+impl Sort {
+    /// Orders two rows, always putting queues that
+    /// weren't found last whatever the direction.
+    fn compare(&self, a: &Row, b: &Row) -> Ordering {
+        let ordering = match self.key {
+            SortKey::Name => Some(a.queue.cmp(&b.queue)),
+            SortKey::Ready => a.ready.zip(b.ready).map(|(x, y)| x.cmp(&y)),
+            SortKey::Unacked => a.unacked.zip(b.unacked).map(|(x, y)| x.cmp(&y)),
+            SortKey::Total => a.total.zip(b.total).map(|(x, y)| x.cmp(&y)),
+            SortKey::Delivered => a.delivered.zip(b.delivered).map(|(x, y)| x.cmp(&y)),
+            SortKey::Consumers => a.consumers.zip(b.consumers).map(|(x, y)| x.cmp(&y)),
+            SortKey::Rate => a
+                .publish_rate
+                .zip(b.publish_rate)
+                .and_then(|(x, y)| x.partial_cmp(&y)),
+            SortKey::OutRate => a
+                .deliver_rate
+                .zip(b.deliver_rate)
+                .and_then(|(x, y)| x.partial_cmp(&y)),
+        };
+        match ordering {
+            Some(o) if self.descending => o.reverse(),
+            Some(o) => o,
+            None => a.vhost.is_none().cmp(&b.vhost.is_none()),
+        }
+    }
+}
+
+/// One entry of `--filter`. Entries with `*` or `?` are globs,
+/// anything else an exact queue name.
+enum Pattern {
+    Exact(String),
+    Glob(Regex),
+}
+
+impl Pattern {
+    fn new(s: &str) -> Self {
+        if !s.contains(&['*', '?'][..]) {
+            return Pattern::Exact(s.to_string());
+        }
+        let mut re = String::from("^");
+        for c in s.chars() {
+            match c {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+        Pattern::Glob(Regex::new(&re).unwrap())
+    }
+}
+
+/// Which queues to show and in what order.
+pub struct Selection {
+    patterns: Vec<Pattern>,
+    regex: Option<Regex>,
+    vhost: Option<String>,
+    sort: Option<Sort>,
+    top: Option<usize>,
+}
+
+impl Selection {
+    pub fn new(
+        filter: Option<&str>,
+        regex: Option<&str>,
+        vhost: Option<&str>,
+        sort: Option<&str>,
+        top: Option<&str>,
+    ) -> Result<Self, String> {
+        let patterns = filter
+            .map(|f| f.split(',').map(|s| Pattern::new(s.trim())).collect())
+            .unwrap_or_default();
+        let regex = regex
+            .map(|r| Regex::new(r).map_err(|e| format!("invalid --regex: {}", e)))
+            .transpose()?;
+        let sort = sort.map(|s| s.parse::<Sort>()).transpose()?;
+        let top = top
+            .map(|t| {
+                t.parse::<usize>()
+                    .map_err(|_| format!("invalid --top: {}", t))
+            })
+            .transpose()?;
+        Ok(Self {
+            patterns,
+            regex,
+            vhost: vhost.map(String::from),
+            sort,
+            top,
+        })
+    }
+
+    fn accepts(&self, q: &QueueInfo) -> bool {
+        let in_vhost = match &self.vhost {
+            Some(v) => &q.vhost == v,
+            None => true,
+        };
+        let matches = match &self.regex {
+            Some(r) => r.is_match(&q.name),
+            None => true,
+        };
+        in_vhost && matches
+    }
+}
+
+/// One queue of the snapshot. Queues asked for with a filter
+/// but missing from RabbitMQ only have their name set.
+#[derive(Serialize, Debug)]
+pub struct Row {
+    pub queue: String,
+    pub vhost: Option<String>,
+    pub ready: Option<u64>,
+    pub unacked: Option<u64>,
+    pub total: Option<u64>,
+    pub delivered: Option<u64>,
+    pub consumers: Option<u64>,
+    pub publish_rate: Option<f64>,
+    pub deliver_rate: Option<f64>,
+    /// Messages published since the previous tick, in watch mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_since: Option<u64>,
+    /// Messages delivered since the previous tick, in watch mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivered_since: Option<u64>,
+    /// Average publish rate since the baseline was taken.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_avg_rate: Option<f64>,
+    /// Average deliver rate since the baseline was taken.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivered_avg_rate: Option<f64>,
+}
+
+/// Which optional columns are shown.
+#[derive(Debug, Clone, Copy)]
+struct Mode {
+    watching: bool,
+    averaging: bool,
+}
+
+/// Field names of the delimited formats, matching the JSON keys.
+const FIELDS: [&str; 9] = [
+    "queue",
+    "vhost",
+    "ready",
+    "unacked",
+    "total",
+    "delivered",
+    "consumers",
+    "publish_rate",
+    "deliver_rate",
+];
+
+/// Extra fields of watch mode.
+const WATCH_FIELDS: [&str; 2] = ["published_since", "delivered_since"];
+
+/// Extra fields when there is a baseline.
+const AVERAGE_FIELDS: [&str; 2] = ["published_avg_rate", "delivered_avg_rate"];
+
+impl Row {
+    fn new(q: &QueueInfo, baseline: Option<&Baseline>) -> Self {
+        let base = baseline.and_then(|b| b.get(q));
+        let delivered = q
+            .message_stats
+            .deliver_get
+            .saturating_sub(base.map(|c| c.deliver_get).unwrap_or(0));
+        // Queues created after the baseline count from zero. Old
+        // baseline files don't know the publish count.
+        let published = match base {
+            Some(c) => c.publish.map(|p| q.message_stats.publish.saturating_sub(p)),
+            None => Some(q.message_stats.publish),
+        };
+        let (published_avg_rate, delivered_avg_rate) = match baseline {
+            Some(b) => {
+                let secs = b.age().as_secs_f64();
+                let average = |n: u64| if secs > 0.0 { n as f64 / secs } else { 0.0 };
+                (published.map(average), Some(average(delivered)))
+            }
+            None => (None, None),
+        };
+        Self {
+            queue: q.name.clone(),
+            vhost: Some(q.vhost.clone()),
+            ready: Some(q.ready),
+            unacked: Some(q.unacked),
+            total: Some(q.total),
+            delivered: Some(delivered),
+            consumers: Some(q.consumers),
+            publish_rate: Some(q.message_stats.publish_details.rate),
+            deliver_rate: Some(q.message_stats.deliver_get_details.rate),
+            published_since: None,
+            delivered_since: None,
+            published_avg_rate,
+            delivered_avg_rate,
+        }
+    }
+
+    /// Fills in the counts since the previous tick. Counters going
+    /// backwards, e.g. after a queue was recreated, count as zero.
+    fn since(mut self, q: &QueueInfo, previous: &Counters) -> Self {
+        let key = (q.vhost.clone(), q.name.clone());
+        if let Some((published, delivered)) = previous.get(&key) {
+            self.published_since = Some(q.message_stats.publish.saturating_sub(*published));
+            self.delivered_since = Some(q.message_stats.deliver_get.saturating_sub(*delivered));
+        }
+        self
+    }
+
+    fn missing(name: &str) -> Self {
+        Self {
+            queue: name.to_string(),
+            vhost: None,
+            ready: None,
+            unacked: None,
+            total: None,
+            delivered: None,
+            consumers: None,
+            publish_rate: None,
+            deliver_rate: None,
+            published_since: None,
+            delivered_since: None,
+            published_avg_rate: None,
+            delivered_avg_rate: None,
+        }
+    }
+
+    /// Values in `FIELDS` order, followed by `WATCH_FIELDS` when
+    /// watching and `AVERAGE_FIELDS` when there is a baseline,
+    /// empty where unknown.
+    fn fields(&self, mode: Mode) -> Vec<String> {
+        let count = |v: Option<u64>| v.map(|n| n.to_string()).unwrap_or_default();
+        let rate = |v: Option<f64>| v.map(|n| n.to_string()).unwrap_or_default();
+        let mut fields = vec![
+            self.queue.clone(),
+            self.vhost.clone().unwrap_or_default(),
+            count(self.ready),
+            count(self.unacked),
+            count(self.total),
+            count(self.delivered),
+            count(self.consumers),
+            rate(self.publish_rate),
+            rate(self.deliver_rate),
+        ];
+        if mode.watching {
+            fields.push(count(self.published_since));
+            fields.push(count(self.delivered_since));
+        }
+        if mode.averaging {
+            fields.push(rate(self.published_avg_rate));
+            fields.push(rate(self.delivered_avg_rate));
+        }
+        fields
+    }
+}
+
+/// Prints the table header.
+fn print_table_header(name_w: usize, mode: Mode) {
+    let mut extra = String::new();
+    let mut rule = String::new();
+    if mode.watching {
+        extra.push_str(&format!("  {:>7}  {:>7}", "+IN", "+OUT"));
+        rule.push_str(&format!("  {}  {}", "-".repeat(7), "-".repeat(7)));
+    }
+    if mode.averaging {
+        extra.push_str(&format!("  {:>9}  {:>9}", "AVG IN/s", "AVG OUT/s"));
+        rule.push_str(&format!("  {}  {}", "-".repeat(9), "-".repeat(9)));
+    }
+    println!(
+        "  {:<nw$}  {:>5}  {:>6}  {:>5}  {:>9}  {:>4}  {:>8}  {:>8}{}",
+        "QUEUE",
+        "READY",
+        "UNACKD",
+        "TOTAL",
+        "DELIVERED",
+        "CONS",
+        "IN/s",
+        "OUT/s",
+        extra,
+        nw = name_w
+    );
+    println!(
+        "  {:<nw$}  {:>5}  {:>6}  {:>5}  {:>9}  {:>4}  {:>8}  {:>8}{}",
+        "-".repeat(name_w),
+        "-----",
+        "------",
+        "-----",
+        "---------",
+        "----",
+        "--------",
+        "--------",
+        rule,
+        nw = name_w
+    );
+}
+
+/// Prints a table row, with a dash for anything unknown.
+fn print_table_row(r: &Row, name_w: usize, mode: Mode) {
+    let count = |v: Option<u64>| v.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+    let rate = |v: Option<f64>| {
+        v.map(|n| format!("{:.1}", n))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let mut extra = String::new();
+    if mode.watching {
+        extra.push_str(&format!(
+            "  {:>7}  {:>7}",
+            count(r.published_since),
+            count(r.delivered_since)
+        ));
+    }
+    if mode.averaging {
+        extra.push_str(&format!(
+            "  {:>9}  {:>9}",
+            rate(r.published_avg_rate),
+            rate(r.delivered_avg_rate)
+        ));
+    }
+    println!(
+        "  {:<nw$}  {:>5}  {:>6}  {:>5}  {:>9}  {:>4}  {:>8}  {:>8}{}",
+        r.queue,
+        count(r.ready),
+        count(r.unacked),
+        count(r.total),
+        count(r.delivered),
+        count(r.consumers),
+        rate(r.publish_rate),
+        rate(r.deliver_rate),
+        extra,
+        nw = name_w
+    );
+}
+
+fn print_table(rows: &[Row], mode: Mode) {
+    if rows.is_empty() {
+        println!("  (no queues found)");
+        return;
+    }
+    let name_w = rows.iter().map(|r| r.queue.len()).max().unwrap_or(5).max(5);
+    print_table_header(name_w, mode);
+    for r in rows {
+        print_table_row(r, name_w, mode);
+    }
+}
+
+/// Quotes a CSV field when it holds a delimiter, quote or newline.
+pub fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// TSV has no quoting, so tabs and newlines become spaces.
+pub fn tsv_field(s: &str) -> String {
+    s.replace(&['\t', '\n', '\r'][..], " ")
+}
+
+fn print_delimited(rows: &[Row], sep: &str, field: fn(&str) -> String, mode: Mode) {
+    let mut names = FIELDS.to_vec();
+    if mode.watching {
+        names.extend(WATCH_FIELDS.iter());
+    }
+    if mode.averaging {
+        names.extend(AVERAGE_FIELDS.iter());
+    }
+    println!("{}", names.join(sep));
+    for r in rows {
+        let fields: Vec<String> = r.fields(mode).iter().map(|f| field(f)).collect();
+        println!("{}", fields.join(sep));
+    }
+}
+
+fn rows(
+    queues: &[QueueInfo],
+    selection: &Selection,
+    baseline: Option<&Baseline>,
+    previous: &Counters,
+) -> Vec<Row> {
+    let mut candidates: Vec<&QueueInfo> = queues.iter().filter(|q| selection.accepts(q)).collect();
+    candidates.sort_by(|a, b| a.name.cmp(&b.name));
+    let row = |q: &QueueInfo| Row::new(q, baseline).since(q, previous);
+    let mut rows: Vec<Row> = if selection.patterns.is_empty() {
+        candidates.iter().map(|q| row(q)).collect()
+    } else {
+        // Exact names keep their order and show up even when missing,
+        // in every vhost that has them. Globs add their matches by name.
+        // Queues, and missing names, are listed once.
+        let mut seen = HashSet::new();
+        let mut rows = Vec::new();
+        for pattern in &selection.patterns {
+            let matches: Vec<&&QueueInfo> = match pattern {
+                Pattern::Exact(name) => candidates.iter().filter(|q| &q.name == name).collect(),
+                Pattern::Glob(re) => candidates.iter().filter(|q| re.is_match(&q.name)).collect(),
+            };
+            if let Pattern::Exact(name) = pattern {
+                if matches.is_empty() && seen.insert((None, name.as_str())) {
+                    rows.push(Row::missing(name));
+                }
+            }
+            for q in matches {
+                if seen.insert((Some(q.vhost.as_str()), q.name.as_str())) {
+                    rows.push(row(q));
+                }
+            }
+        }
+        rows
+    };
+    if let Some(sort) = &selection.sort {
+        rows.sort_by(|a, b| sort.compare(a, b));
+    }
+    if let Some(n) = selection.top {
+        rows.truncate(n);
+    }
+    rows
+}
+
+fn render(rows: &[Row], format: Format, watching: bool, baseline: Option<&Baseline>) {
+    let mode = Mode {
+        watching,
+        averaging: baseline.is_some(),
+    };
+    match format {
+        Format::Table => {
+            if let Some(b) = baseline {
+                println!("  Baseline taken {} ago\n", format_age(b.age()));
+            }
+            print_table(rows, mode)
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&rows).unwrap()),
+        Format::Csv => print_delimited(rows, ",", csv_field, mode),
+        Format::Tsv => print_delimited(rows, "\t", tsv_field, mode),
+    }
+}
+
+/// Print a one-shot view of queue states to stdout.
+/// If baseline_path is provided, on first run (file missing) or with
+/// `reset` saves current counters and shows 0. On subsequent runs shows
+/// the delta from the baseline and the average rates since it was taken.
+pub fn print<M: ManagementClient>(
+    client: &M,
+    selection: &Selection,
+    baseline_path: Option<&Path>,
+    reset: bool,
+    format: Format,
+) -> Result<(), String> {
+    let queues = client.get_queues_info()?;
+    let baseline = baseline_path.map(|p| Baseline::resolve(p, reset, &queues));
+    let rows = rows(&queues, selection, baseline.as_ref(), &Counters::new());
+    render(&rows, format, false, baseline.as_ref());
+    Ok(())
+}
+
+/// Prints the snapshot every `interval` until interrupted, using the
+/// same client throughout. Next to the broker's rates each row shows
+/// the messages published and delivered since the previous tick. The
+/// table is redrawn in place on a terminal; other formats, or output
+/// that isn't a terminal, get one snapshot after another. A failed
+/// fetch is reported and tried again on the next tick.
+pub fn watch<M: ManagementClient>(
+    client: &M,
+    selection: &Selection,
+    baseline_path: Option<&Path>,
+    reset: bool,
+    format: Format,
+    interval: Duration,
+) {
+    let redraw = format == Format::Table && termion::is_tty(&io::stdout());
+    let mut baseline: Option<Option<Baseline>> = None;
+    let mut previous = Counters::new();
+    loop {
+        let queues = match client.get_queues_info() {
+            Ok(q) => q,
+            Err(e) => {
+                eprintln!("{}", e);
+                thread::sleep(interval);
+                continue;
+            }
+        };
+        let baseline = baseline
+            .get_or_insert_with(|| baseline_path.map(|p| Baseline::resolve(p, reset, &queues)))
+            .as_ref();
+        let rows = rows(&queues, selection, baseline, &previous);
+        if redraw {
+            print!("{}{}", clear::All, cursor::Goto(1, 1));
+            println!("Every {}s, Ctrl-C to stop\n", interval.as_secs());
+        }
+        render(&rows, format, true, baseline);
+        let _ = io::stdout().flush();
+        previous = queues
+            .iter()
+            .map(|q| {
+                let key = (q.vhost.clone(), q.name.clone());
+                (key, (q.message_stats.publish, q.message_stats.deliver_get))
+            })
+            .collect();
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(vhost: &str, name: &str, ready: u64) -> QueueInfo {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "vhost": vhost,
+            "type": "classic",
+            "state": "running",
+            "messages_ready": ready,
+            "messages_unacknowledged": 0,
+            "messages": ready,
+        }))
+        .unwrap()
+    }
+
+    fn names(queues: &[QueueInfo], filter: Option<&str>, sort: Option<&str>) -> Vec<String> {
+        let selection = Selection::new(filter, None, None, sort, None).unwrap();
+        rows(queues, &selection, None, &Counters::new())
+            .into_iter()
+            .map(|r| r.queue)
+            .collect()
+    }
+
+    #[test]
+    fn parses_sort() {
+        let sort: Sort = "ready".parse().unwrap();
+        assert_eq!((sort.key, sort.descending), (SortKey::Ready, true));
+        let sort: Sort = "name".parse().unwrap();
+        assert_eq!((sort.key, sort.descending), (SortKey::Name, false));
+        let sort: Sort = "name:desc".parse().unwrap();
+        assert!(sort.descending);
+        assert!("size".parse::<Sort>().is_err());
+        assert!("ready:up".parse::<Sort>().is_err());
+    }
+
+    #[test]
+    fn globs_match_whole_names() {
+        let glob = |p: &str, name: &str| match Pattern::new(p) {
+            Pattern::Glob(re) => re.is_match(name),
+            Pattern::Exact(_) => panic!("{} is not a glob", p),
+        };
+        assert!(glob("orders.*", "orders.created"));
+        assert!(!glob("orders.*", "old.orders.created"));
+        assert!(glob("q?", "q1"));
+        assert!(!glob("q?", "q10"));
+        assert!(matches!(Pattern::new("orders.created"), Pattern::Exact(_)));
+    }
+
+    #[test]
+    fn exact_names_keep_their_order_and_missing_ones_show() {
+        let queues = [queue("/", "a", 1), queue("/", "b", 2)];
+        assert_eq!(names(&queues, Some("b, gone, a"), None), ["b", "gone", "a"]);
+        assert_eq!(names(&queues, Some("*, a"), None), ["a", "b"]);
+        assert_eq!(names(&queues, Some("gone, gone"), None), ["gone"]);
+    }
+
+    #[test]
+    fn exact_names_match_in_every_vhost() {
+        let queues = [
+            queue("/", "a", 1),
+            queue("other", "a", 2),
+            queue("/", "b", 3),
+        ];
+        let selection = Selection::new(Some("a"), None, None, None, None).unwrap();
+        let vhosts: Vec<Option<String>> = rows(&queues, &selection, None, &Counters::new())
+            .into_iter()
+            .map(|r| r.vhost)
+            .collect();
+        assert_eq!(vhosts, [Some("/".to_string()), Some("other".to_string())]);
+    }
+
+    #[test]
+    fn sorts_busiest_first_and_missing_last() {
+        let queues = [queue("/", "a", 1), queue("/", "b", 5), queue("/", "c", 3)];
+        assert_eq!(names(&queues, None, Some("ready")), ["b", "c", "a"]);
+        assert_eq!(names(&queues, None, Some("ready:asc")), ["a", "c", "b"]);
+        assert_eq!(
+            names(&queues, Some("gone,a,b"), Some("ready:asc")),
+            ["a", "b", "gone"]
+        );
+    }
+}
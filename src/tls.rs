@@ -0,0 +1,102 @@
+use crate::config::TlsConfig;
+
+use std::{fs, path::Path};
+
+use openssl::{pkcs12::Pkcs12, pkey::PKey, stack::Stack, x509::X509};
+use reqwest::{blocking::ClientBuilder, Certificate, Identity, Url};
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Every certificate of a PEM bundle. reqwest only takes one
+/// certificate at a time.
+fn ca_bundle(path: &Path) -> Result<Vec<Certificate>, String> {
+    let err = |e: &dyn ToString| format!("{}: {}", path.display(), e.to_string());
+    let certs = X509::stack_from_pem(&read(path)?).map_err(|e| err(&e))?;
+    if certs.is_empty() {
+        return Err(err(&"no certificates found"));
+    }
+    certs
+        .iter()
+        .map(|c| {
+            let der = c.to_der().map_err(|e| err(&e))?;
+            Certificate::from_der(&der).map_err(|e| err(&e))
+        })
+        .collect()
+}
+
+/// Bundles a PEM certificate, with any chain after it, and its
+/// key into PKCS#12, the only client identity native-tls takes.
+fn pem_identity(cert: &Path, key: &Path) -> Result<Identity, String> {
+    let mut chain = X509::stack_from_pem(&read(cert)?)
+        .map_err(|e| format!("{}: {}", cert.display(), e))?
+        .into_iter();
+    let leaf = chain
+        .next()
+        .ok_or_else(|| format!("{}: no certificates found", cert.display()))?;
+    let pkey =
+        PKey::private_key_from_pem(&read(key)?).map_err(|e| format!("{}: {}", key.display(), e))?;
+    let mut ca = Stack::new().map_err(|e| e.to_string())?;
+    for c in chain {
+        ca.push(c).map_err(|e| e.to_string())?;
+    }
+    let der = Pkcs12::builder()
+        .name("rabbitui")
+        .pkey(&pkey)
+        .cert(&leaf)
+        .ca(ca)
+        .build2("")
+        .and_then(|p| p.to_der())
+        .map_err(|e| format!("{}: {}", cert.display(), e))?;
+    Identity::from_pkcs12_der(&der, "").map_err(|e| e.to_string())
+}
+
+fn identity(tls: &TlsConfig) -> Result<Option<Identity>, String> {
+    match (&tls.client_pkcs12, &tls.client_cert, &tls.client_key) {
+        (Some(path), _, _) => {
+            let password = tls.pkcs12_password.as_deref().unwrap_or("");
+            Identity::from_pkcs12_der(&read(path)?, password)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", path.display(), e))
+        }
+        (None, Some(cert), Some(key)) => pem_identity(cert, key).map(Some),
+        (None, Some(_), None) => Err("client_cert needs a client_key".to_string()),
+        (None, None, Some(_)) => Err("client_key needs a client_cert".to_string()),
+        (None, None, None) => Ok(None),
+    }
+}
+
+/// Applies the TLS settings to a client builder.
+pub fn configure(mut builder: ClientBuilder, tls: &TlsConfig) -> Result<ClientBuilder, String> {
+    if let Some(path) = &tls.ca_cert {
+        for cert in ca_bundle(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(id) = identity(tls)? {
+        builder = builder.identity(id);
+    }
+    Ok(builder
+        .danger_accept_invalid_certs(tls.insecure)
+        .danger_accept_invalid_hostnames(tls.insecure))
+}
+
+/// Sends requests for `addr` to `name` instead, with `name` pinned
+/// to the addresses `addr` resolves to. The broker is still reached
+/// at `addr`, but `name` is what goes in the SNI extension and what
+/// its certificate is checked against. Returns the address to use.
+pub fn server_name(
+    builder: ClientBuilder,
+    addr: &str,
+    name: &str,
+) -> Result<(ClientBuilder, String), String> {
+    let mut url = Url::parse(addr).map_err(|e| format!("{}: {}", addr, e))?;
+    let targets = url
+        .socket_addrs(|| None)
+        .map_err(|e| format!("{}: {}", addr, e))?;
+    url.set_host(Some(name))
+        .map_err(|e| format!("server name {}: {}", name, e))?;
+    let addr = url.as_str().trim_end_matches('/').to_string();
+    Ok((builder.resolve_to_addrs(name, &targets), addr))
+}
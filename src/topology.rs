@@ -0,0 +1,458 @@
+use crate::{
+    models::{ExchangeBindings, ExchangeInfo, QueueInfo},
+    Rowable,
+};
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+pub const BRANCH: &str = "├── ";
+pub const LAST_BRANCH: &str = "└── ";
+pub const PIPE: &str = "│   ";
+pub const SPACE: &str = "    ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Exchange,
+    Queue,
+}
+
+/// A single line of the rendered topology graph.
+#[derive(Debug, Clone)]
+pub struct TopologyNode {
+    /// Box-drawing characters that place this node in the tree.
+    pub prefix: String,
+    pub name: String,
+    pub kind: NodeKind,
+    /// Exchange type for exchanges, queue type for queues.
+    pub t: String,
+    /// Routing key of the binding that leads to this node,
+    /// `None` for roots.
+    pub routing_key: Option<String>,
+    /// Exchanges with no outbound bindings and queues
+    /// with no inbound bindings.
+    pub dangling: bool,
+    /// Set when this node was already drawn further up the
+    /// same branch. Its children are not repeated.
+    pub cycle: bool,
+}
+
+/// A queue a simulated message would land in, along with
+/// the exchanges it passed through to get there.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub queue: String,
+    pub path: Vec<String>,
+}
+
+impl Route {
+    pub fn headers<'a>() -> [&'a str; 2] {
+        ["Queue", "Path"]
+    }
+}
+
+impl Rowable for Route {
+    fn to_row(&self) -> Vec<String> {
+        let path: Vec<&str> = self
+            .path
+            .iter()
+            .map(|p| if p.is_empty() { "(AMQP DEFAULT)" } else { p })
+            .collect();
+        vec![self.queue.clone(), path.join(" → ")]
+    }
+}
+
+/// AMQP topic matching. Words are separated by dots, `*`
+/// matches exactly one word and `#` matches zero or more.
+pub fn topic_matches(pattern: &str, key: &str) -> bool {
+    fn matches(pattern: &[&str], key: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => key.is_empty(),
+            Some((&"#", rest)) => (0..=key.len()).any(|i| matches(rest, &key[i..])),
+            Some((&word, rest)) => match key.split_first() {
+                Some((k, key_rest)) => (word == "*" || word == *k) && matches(rest, key_rest),
+                None => false,
+            },
+        }
+    }
+    // An empty key or pattern is zero words, not one empty word.
+    fn words(s: &str) -> Vec<&str> {
+        if s.is_empty() {
+            Vec::new()
+        } else {
+            s.split('.').collect()
+        }
+    }
+    matches(&words(pattern), &words(key))
+}
+
+/// Headers exchange matching against the binding arguments.
+/// `x-match` selects between `all` (the default) and `any`.
+/// Other `x-` arguments are only compared for the `-with-x`
+/// variants.
+fn headers_match(arguments: &HashMap<String, Value>, headers: &HashMap<String, String>) -> bool {
+    let mode = arguments
+        .get("x-match")
+        .and_then(|v| v.as_str())
+        .unwrap_or("all");
+    let with_x = mode.ends_with("-with-x");
+    let mut expected = arguments
+        .iter()
+        .filter(|(k, _)| with_x || !k.starts_with("x-"))
+        .filter(|(k, _)| k.as_str() != "x-match")
+        .peekable();
+    if expected.peek().is_none() {
+        return mode.starts_with("all");
+    }
+    let mut results = expected.map(|(k, v)| {
+        let expected = match v {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        headers.get(k) == Some(&expected)
+    });
+    if mode.starts_with("any") {
+        results.any(|r| r)
+    } else {
+        results.all(|r| r)
+    }
+}
+
+/// Snapshot of everything needed to describe how messages
+/// flow through a broker.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    pub exchanges: Vec<ExchangeInfo>,
+    pub queues: Vec<QueueInfo>,
+    pub bindings: Vec<ExchangeBindings>,
+}
+
+impl Topology {
+    pub fn new(
+        exchanges: Vec<ExchangeInfo>,
+        queues: Vec<QueueInfo>,
+        bindings: Vec<ExchangeBindings>,
+    ) -> Self {
+        Self {
+            exchanges,
+            queues,
+            bindings,
+        }
+    }
+
+    /// Sorted, de-duplicated list of every vhost that has
+    /// at least one exchange or queue.
+    pub fn vhosts(&self) -> Vec<String> {
+        let mut vhosts: Vec<String> = self
+            .exchanges
+            .iter()
+            .map(|e| e.vhost.clone())
+            .chain(self.queues.iter().map(|q| q.vhost.clone()))
+            .collect();
+        vhosts.sort();
+        vhosts.dedup();
+        vhosts
+    }
+
+    pub fn exchange(&self, vhost: &str, name: &str) -> Option<&ExchangeInfo> {
+        self.exchanges
+            .iter()
+            .find(|e| e.vhost == vhost && e.name == name)
+    }
+
+    /// Bindings going out of the given exchange, sorted by
+    /// destination so the graph is stable between refreshes.
+    pub fn outbound(&self, vhost: &str, exchange: &str) -> Vec<&ExchangeBindings> {
+        let mut out: Vec<&ExchangeBindings> = self
+            .bindings
+            .iter()
+            .filter(|b| b.vhost == vhost && b.source == exchange)
+            .collect();
+        out.sort_by(|a, b| (&a.dest, &a.routing_key).cmp(&(&b.dest, &b.routing_key)));
+        out
+    }
+
+    /// Whether anything other than the default exchange routes
+    /// into the given destination. Every queue is implicitly bound
+    /// to the default exchange, so those bindings don't count.
+    fn has_inbound(&self, vhost: &str, dest: &str, dest_type: &str) -> bool {
+        self.bindings.iter().any(|b| {
+            b.vhost == vhost && !b.source.is_empty() && b.dest == dest && b.dest_type == dest_type
+        })
+    }
+
+    /// Simulates publishing to `exchange` without touching the broker,
+    /// following exchange-to-exchange bindings. Each queue is only
+    /// listed once, with the first path that reached it.
+    pub fn route(
+        &self,
+        vhost: &str,
+        exchange: &str,
+        routing_key: &str,
+        headers: &HashMap<String, String>,
+    ) -> Vec<Route> {
+        let mut routes: Vec<Route> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![(exchange.to_owned(), vec![exchange.to_owned()])];
+        while let Some((name, path)) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            if name.is_empty() {
+                // The default exchange delivers straight to the
+                // queue named by the routing key.
+                if self
+                    .queues
+                    .iter()
+                    .any(|q| q.vhost == vhost && q.name == routing_key)
+                {
+                    routes.push(Route {
+                        queue: routing_key.to_owned(),
+                        path,
+                    });
+                }
+                continue;
+            }
+            let t = match self.exchange(vhost, &name) {
+                Some(e) => e.t.as_str(),
+                None => continue,
+            };
+            for b in self.outbound(vhost, &name) {
+                let matched = match t {
+                    "fanout" => true,
+                    "direct" => b.routing_key == routing_key,
+                    "topic" => topic_matches(&b.routing_key, routing_key),
+                    "headers" => headers_match(&b.arguments, headers),
+                    _ => false,
+                };
+                if !matched {
+                    continue;
+                }
+                let mut next_path = path.clone();
+                next_path.push(b.dest.clone());
+                if b.dest_type == "exchange" {
+                    pending.push((b.dest.clone(), next_path));
+                } else if !routes.iter().any(|r| r.queue == b.dest) {
+                    routes.push(Route {
+                        queue: b.dest.clone(),
+                        path: next_path,
+                    });
+                }
+            }
+        }
+        routes.sort_by(|a, b| a.queue.cmp(&b.queue));
+        routes
+    }
+
+    /// Flattens the vhost into tree lines. Roots are exchanges
+    /// that nothing else routes into, followed by queues that
+    /// have no inbound bindings at all. If `focus` is given only
+    /// the subtree of that exchange is produced.
+    pub fn graph(&self, vhost: &str, focus: Option<&str>) -> Vec<TopologyNode> {
+        let mut nodes = Vec::new();
+        if let Some(name) = focus {
+            let mut path = HashSet::new();
+            self.walk_exchange(vhost, name, None, "", "", &mut path, &mut nodes);
+            return nodes;
+        }
+
+        let mut exchanges: Vec<&ExchangeInfo> = self
+            .exchanges
+            .iter()
+            .filter(|e| e.vhost == vhost && !e.name.is_empty())
+            // Built-in amq.* exchanges only add noise unless
+            // someone actually bound something to them.
+            .filter(|e| !e.name.starts_with("amq.") || !self.outbound(vhost, &e.name).is_empty())
+            .collect();
+        exchanges.sort_by(|a, b| a.name.cmp(&b.name));
+        let roots: Vec<&ExchangeInfo> = exchanges
+            .iter()
+            .filter(|e| !self.has_inbound(vhost, &e.name, "exchange"))
+            .copied()
+            .collect();
+        for e in &roots {
+            let mut path = HashSet::new();
+            self.walk_exchange(vhost, &e.name, None, "", "", &mut path, &mut nodes);
+        }
+        // Exchanges that only receive from each other in a
+        // loop never show up as a root, so draw them last.
+        for e in &exchanges {
+            let drawn = nodes
+                .iter()
+                .any(|n| n.kind == NodeKind::Exchange && n.name == e.name);
+            if !drawn {
+                let mut path = HashSet::new();
+                self.walk_exchange(vhost, &e.name, None, "", "", &mut path, &mut nodes);
+            }
+        }
+
+        let mut queues: Vec<&QueueInfo> = self
+            .queues
+            .iter()
+            .filter(|q| q.vhost == vhost && !self.has_inbound(vhost, &q.name, "queue"))
+            .collect();
+        queues.sort_by(|a, b| a.name.cmp(&b.name));
+        nodes.extend(queues.iter().map(|q| TopologyNode {
+            prefix: String::new(),
+            name: q.name.clone(),
+            kind: NodeKind::Queue,
+            t: q.t.clone(),
+            routing_key: None,
+            dangling: true,
+            cycle: false,
+        }));
+        nodes
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn walk_exchange(
+        &self,
+        vhost: &str,
+        name: &str,
+        routing_key: Option<&str>,
+        prefix: &str,
+        child_prefix: &str,
+        path: &mut HashSet<String>,
+        nodes: &mut Vec<TopologyNode>,
+    ) {
+        let outbound = self.outbound(vhost, name);
+        let cycle = path.contains(name);
+        nodes.push(TopologyNode {
+            prefix: prefix.to_owned(),
+            name: name.to_owned(),
+            kind: NodeKind::Exchange,
+            t: self
+                .exchange(vhost, name)
+                .map(|e| e.t.clone())
+                .unwrap_or_default(),
+            routing_key: routing_key.map(|k| k.to_owned()),
+            dangling: outbound.is_empty(),
+            cycle,
+        });
+        if cycle {
+            return;
+        }
+
+        path.insert(name.to_owned());
+        for (i, b) in outbound.iter().enumerate() {
+            let last = i == outbound.len() - 1;
+            let (branch, pipe) = if last {
+                (LAST_BRANCH, SPACE)
+            } else {
+                (BRANCH, PIPE)
+            };
+            let branch = format!("{}{}", child_prefix, branch);
+            let next_prefix = format!("{}{}", child_prefix, pipe);
+            if b.dest_type == "exchange" {
+                self.walk_exchange(
+                    vhost,
+                    &b.dest,
+                    Some(&b.routing_key),
+                    &branch,
+                    &next_prefix,
+                    path,
+                    nodes,
+                );
+            } else {
+                let t = self
+                    .queues
+                    .iter()
+                    .find(|q| q.vhost == vhost && q.name == b.dest)
+                    .map(|q| q.t.clone())
+                    .unwrap_or_default();
+                nodes.push(TopologyNode {
+                    prefix: branch,
+                    name: b.dest.clone(),
+                    kind: NodeKind::Queue,
+                    t,
+                    routing_key: Some(b.routing_key.clone()),
+                    dangling: false,
+                    cycle: false,
+                });
+            }
+        }
+        path.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_words() {
+        assert!(topic_matches("a.b", "a.b"));
+        assert!(topic_matches("a.*", "a.b"));
+        assert!(!topic_matches("a.*", "a"));
+        assert!(!topic_matches("a.*", "a.b.c"));
+        assert!(!topic_matches("a.b", "a.c"));
+    }
+
+    #[test]
+    fn topic_hash_matches_zero_or_more() {
+        assert!(topic_matches("#", ""));
+        assert!(topic_matches("#", "a.b"));
+        assert!(topic_matches("a.#", "a"));
+        assert!(topic_matches("a.#", "a.b.c"));
+        assert!(topic_matches("#.c", "a.b.c"));
+        assert!(topic_matches("a.#.c", "a.c"));
+        assert!(!topic_matches("a.#", "b.a"));
+    }
+
+    #[test]
+    fn topic_empty_key() {
+        assert!(topic_matches("", ""));
+        assert!(!topic_matches("", "a"));
+        assert!(!topic_matches("a", ""));
+        assert!(!topic_matches("*", ""));
+    }
+
+    fn arguments(value: serde_json::Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn headers_all_and_any() {
+        let all = arguments(serde_json::json!({"format": "pdf", "size": 2}));
+        let any = arguments(serde_json::json!({"x-match": "any", "format": "pdf", "size": 2}));
+        let pdf = headers(&[("format", "pdf")]);
+        let both = headers(&[("format", "pdf"), ("size", "2")]);
+        assert!(!headers_match(&all, &pdf));
+        assert!(headers_match(&all, &both));
+        assert!(headers_match(&any, &pdf));
+        assert!(!headers_match(&any, &headers(&[("format", "zip")])));
+    }
+
+    #[test]
+    fn headers_x_arguments_only_with_x() {
+        let plain = arguments(serde_json::json!({"x-tenant": "a", "format": "pdf"}));
+        let with_x = arguments(
+            serde_json::json!({"x-match": "all-with-x", "x-tenant": "a", "format": "pdf"}),
+        );
+        let pdf = headers(&[("format", "pdf")]);
+        assert!(headers_match(&plain, &pdf));
+        assert!(!headers_match(&with_x, &pdf));
+        assert!(headers_match(
+            &with_x,
+            &headers(&[("format", "pdf"), ("x-tenant", "a")])
+        ));
+    }
+
+    #[test]
+    fn headers_without_arguments() {
+        let none = headers(&[]);
+        assert!(headers_match(&arguments(serde_json::json!({})), &none));
+        assert!(!headers_match(
+            &arguments(serde_json::json!({"x-match": "any"})),
+            &none
+        ));
+    }
+}
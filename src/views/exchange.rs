@@ -1,11 +1,20 @@
 use super::{centered_rect, Drawable, StatefulPane};
 use crate::{
     models::{ExchangeBindings, ExchangeInfo},
-    widgets::help::Help,
+    topology::{Route, Topology},
+    widgets::{
+        form::{Form, FormAction},
+        help::Help,
+        notif::Notification,
+        policy::PolicyPopup,
+    },
     DataContainer, Datatable, ManagementClient, Rowable,
 };
 
-use std::sync::{mpsc, Arc};
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+};
 
 use termion::event::Key;
 use tui::{
@@ -25,7 +34,14 @@ Keys:
   - k: previous row
   - j: next row
   - return: open/close drilldown for selected exchange
-  - ?: close the help menu";
+  - i: open/close effective policy breakdown for selected exchange
+  - s: simulate routing a key through the selected exchange
+  - esc: close simulation results
+  - ?: close the help menu
+
+The simulator evaluates bindings locally and never publishes. \
+For headers exchanges, headers are given as key=value pairs \
+separated by commas.";
 
 pub struct ExchangePane<M>
 where
@@ -33,9 +49,15 @@ where
 {
     table: Datatable<ExchangeInfo>,
     bindings_table: Datatable<ExchangeBindings>,
+    routes_table: Datatable<Route>,
+    policy: PolicyPopup,
+    simulator: Option<Form>,
+    notif: Option<Notification>,
     data_chan: mpsc::Receiver<Vec<ExchangeInfo>>,
     should_fetch_bindings: bool,
     should_draw_popout: bool,
+    should_draw_routes: bool,
+    should_draw_policy: bool,
     should_show_help: bool,
     client: Arc<M>,
 }
@@ -45,14 +67,21 @@ where
     M: ManagementClient,
 {
     pub fn new(client: Arc<M>, data_chan: mpsc::Receiver<Vec<ExchangeInfo>>) -> Self {
-        let data = client.get_exchange_overview();
+        // Left empty if this fails, the data thread fills it in.
+        let data = client.get_exchange_overview().unwrap_or_default();
         let table = Datatable::<ExchangeInfo>::new(data);
         Self {
             table,
             data_chan,
             bindings_table: Datatable::default(),
+            routes_table: Datatable::default(),
+            policy: PolicyPopup::default(),
+            simulator: None,
+            notif: None,
             should_fetch_bindings: false,
             should_draw_popout: false,
+            should_draw_routes: false,
+            should_draw_policy: false,
             should_show_help: false,
             client: Arc::clone(&client),
         }
@@ -89,8 +118,97 @@ where
         f.render_stateful_widget(b_t, pop_area, &mut self.bindings_table.state);
     }
 
+    fn draw_routes<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let data = self.routes_table.data.get();
+        let header_lits = Route::headers();
+        let header_cells = header_lits
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow)));
+        let header = Row::new(header_cells)
+            .style(Style::default())
+            .height(1)
+            .bottom_margin(1);
+        let rows = data.iter().map(|r| {
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).bottom_margin(1)
+        });
+        let title = if data.is_empty() {
+            "Simulated Routes - no queues would receive this message"
+        } else {
+            "Simulated Routes"
+        };
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let t = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[Constraint::Percentage(35), Constraint::Percentage(65)]);
+        let pop_area = centered_rect(70, 50, area);
+        f.render_widget(Clear, pop_area);
+        f.render_stateful_widget(t, pop_area, &mut self.routes_table.state);
+    }
+
+    fn fetch_policy_details(&mut self) {
+        if let Some(i) = self.table.state.selected() {
+            let exch = &self.table.data.get()[i];
+            self.policy = match self.client.get_policies() {
+                Ok(policies) => PolicyPopup::new(exch.policy_details(&policies)),
+                Err(e) => PolicyPopup::failed(e),
+            };
+        }
+    }
+
+    fn open_simulator(&mut self) {
+        if let Some(i) = self.table.state.selected() {
+            let exch = &self.table.data.get()[i];
+            let mut form = Form::new("Simulate routing").field("Routing key", "");
+            if exch.t == "headers" {
+                form = form.field("Headers (key=value, ...)", "");
+            }
+            self.simulator = Some(form);
+        }
+    }
+
+    /// Evaluates the simulator form against freshly fetched
+    /// bindings for the selected exchange.
+    fn run_simulation(&mut self, form: &Form) {
+        if let Some(i) = self.table.state.selected() {
+            let exch = &self.table.data.get()[i];
+            let headers: HashMap<String, String> = if exch.t == "headers" {
+                form.value(1)
+                    .split(',')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+            let fetched = self
+                .client
+                .get_queues_info()
+                .and_then(|q| Ok((q, self.client.get_bindings()?)));
+            let (queues, bindings) = match fetched {
+                Ok(f) => f,
+                Err(e) => {
+                    self.notif = Some(Notification::new(e));
+                    return;
+                }
+            };
+            let topology = Topology::new(self.table.data.get().clone(), queues, bindings);
+            let routes = topology.route(&exch.vhost, &exch.name, form.value(0), &headers);
+            self.routes_table = Datatable::<Route>::new(routes);
+            self.should_draw_routes = true;
+        }
+    }
+
     fn forward_table(&mut self) {
-        if self.should_draw_popout {
+        if self.should_draw_routes {
+            self.routes_table.next();
+        } else if self.should_draw_policy {
+            self.policy.next();
+        } else if self.should_draw_popout {
             self.bindings_table.next();
         } else {
             self.table.next();
@@ -98,7 +216,11 @@ where
     }
 
     fn back_table(&mut self) {
-        if self.should_draw_popout {
+        if self.should_draw_routes {
+            self.routes_table.previous();
+        } else if self.should_draw_policy {
+            self.policy.previous();
+        } else if self.should_draw_popout {
             self.bindings_table.previous();
         } else {
             self.table.previous();
@@ -141,8 +263,9 @@ where
             .highlight_style(selected_style)
             .highlight_symbol(">> ")
             .widths(&[
-                Constraint::Percentage(40),
-                Constraint::Percentage(30),
+                Constraint::Percentage(35),
+                Constraint::Percentage(15),
+                Constraint::Percentage(20),
                 Constraint::Percentage(15),
                 Constraint::Percentage(15),
             ]);
@@ -153,15 +276,35 @@ where
                 Some(i) => {
                     if self.should_fetch_bindings {
                         let drilldown = &row_data[i];
-                        let binding_data = self.client.get_exchange_bindings(drilldown);
-                        self.bindings_table = Datatable::<ExchangeBindings>::new(binding_data);
+                        match self.client.get_exchange_bindings(drilldown) {
+                            Ok(d) => self.bindings_table = Datatable::<ExchangeBindings>::new(d),
+                            Err(e) => {
+                                self.notif = Some(Notification::new(e));
+                                self.should_draw_popout = false;
+                            }
+                        }
                         self.should_fetch_bindings = false;
                     }
-                    self.draw_popout(f, area);
+                    if self.should_draw_popout {
+                        self.draw_popout(f, area);
+                    }
                 }
             }
         }
 
+        if self.should_draw_policy {
+            self.policy.draw(f, area);
+        }
+        if self.should_draw_routes {
+            self.draw_routes(f, area);
+        }
+        if let Some(form) = &self.simulator {
+            form.draw(f, area);
+        }
+        if let Some(n) = &self.notif {
+            n.draw(f, area);
+        }
+
         if self.should_show_help {
             let help = Help::new(HELP);
             help.draw(f, area);
@@ -175,6 +318,15 @@ where
     B: Backend,
 {
     fn handle_key(&mut self, key: Key) {
+        self.notif = None;
+        if let Some(mut form) = self.simulator.take() {
+            match form.handle_key(key) {
+                FormAction::Submit => self.run_simulation(&form),
+                FormAction::Cancel => {}
+                FormAction::None => self.simulator = Some(form),
+            }
+            return;
+        }
         match key {
             Key::Char('j') => {
                 self.forward_table();
@@ -186,6 +338,19 @@ where
                 self.should_fetch_bindings = true;
                 self.should_draw_popout = !self.should_draw_popout;
             }
+            Key::Char('i') => {
+                if !self.should_draw_policy {
+                    self.fetch_policy_details();
+                }
+                self.should_draw_policy = !self.should_draw_policy;
+            }
+            Key::Char('s') => {
+                self.should_draw_routes = false;
+                self.open_simulator();
+            }
+            Key::Esc => {
+                self.should_draw_routes = false;
+            }
             Key::Char('?') => {
                 self.should_show_help = !self.should_show_help;
             }
@@ -193,6 +358,10 @@ where
         }
     }
 
+    fn is_capturing_input(&self) -> bool {
+        self.simulator.is_some()
+    }
+
     fn update(&mut self) {
         if let Some(d) = self.data_chan.try_iter().next() {
             self.table.data = DataContainer { entries: d };
@@ -0,0 +1,484 @@
+use super::{draw_error, Drawable, StatefulPane};
+use crate::{
+    models::{FederationLink, Parameter},
+    widgets::{
+        confirmation::ConfirmationBox,
+        form::{Form, FormAction},
+        help::Help,
+        notif::Notification,
+    },
+    DataContainer, Datatable, ManagementClient, Rowable,
+};
+
+use serde_json::{json, Map, Value};
+use std::sync::{mpsc, Arc};
+
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Cell, Row, Table},
+    Frame,
+};
+
+const HELP: &str = "The Federation tab shows the status of every \
+federation link along with the upstreams and upstream sets they \
+are built from. Links that are not running are shown in red.
+
+Keys:
+  - h: previous tab
+  - l: next tab
+  - k: previous row
+  - j: next row
+  - v: switch between links, upstreams and upstream sets
+  - n: create an upstream or upstream set
+  - e: edit selected upstream or upstream set
+  - d: delete selected upstream or upstream set
+  - ?: close the help menu
+
+Several upstream URIs can be given separated by commas. An \
+upstream set lists upstream names separated by commas.";
+
+const UPSTREAM: &str = "federation-upstream";
+const UPSTREAM_SET: &str = "federation-upstream-set";
+
+/// Links, upstreams and upstream sets.
+pub type FederationData = (Vec<FederationLink>, Vec<Parameter>, Vec<Parameter>);
+
+/// Reads the pane's data. Brokers without the federation
+/// plugin have none of it, which is not an error.
+pub fn fetch<M: ManagementClient>(client: &M) -> Result<FederationData, String> {
+    Ok((
+        client.get_federation_links()?,
+        client.get_parameters(UPSTREAM)?,
+        client.get_parameters(UPSTREAM_SET)?,
+    ))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Links,
+    Upstreams,
+    Sets,
+}
+
+impl View {
+    fn next(self) -> Self {
+        match self {
+            View::Links => View::Upstreams,
+            View::Upstreams => View::Sets,
+            View::Sets => View::Links,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            View::Links => "Federation links",
+            View::Upstreams => "Upstreams",
+            View::Sets => "Upstream sets",
+        }
+    }
+
+    fn component(self) -> &'static str {
+        match self {
+            View::Sets => UPSTREAM_SET,
+            _ => UPSTREAM,
+        }
+    }
+}
+
+pub struct FederationPane<'a, M>
+where
+    M: ManagementClient,
+{
+    links: Datatable<FederationLink>,
+    upstreams: Datatable<Parameter>,
+    sets: Datatable<Parameter>,
+    confirmation: ConfirmationBox<'a>,
+    data_chan: mpsc::Receiver<Result<FederationData, String>>,
+    client: Arc<M>,
+    /// Why federation couldn't be fetched, if it couldn't.
+    error: Option<String>,
+    view: View,
+    form: Option<(View, Form)>,
+    notif: Option<Notification>,
+    should_show_help: bool,
+    should_confirm: bool,
+}
+
+impl<'a, M> FederationPane<'a, M>
+where
+    M: ManagementClient,
+{
+    pub fn new(client: Arc<M>, data_chan: mpsc::Receiver<Result<FederationData, String>>) -> Self {
+        Self {
+            links: Datatable::default(),
+            upstreams: Datatable::default(),
+            sets: Datatable::default(),
+            confirmation: ConfirmationBox::default(),
+            data_chan,
+            client: Arc::clone(&client),
+            error: None,
+            view: View::Links,
+            form: None,
+            notif: None,
+            should_show_help: false,
+            should_confirm: false,
+        }
+    }
+
+    fn parameters(&self) -> &Datatable<Parameter> {
+        match self.view {
+            View::Sets => &self.sets,
+            _ => &self.upstreams,
+        }
+    }
+
+    fn selected(&self) -> Option<&Parameter> {
+        let table = self.parameters();
+        table.state.selected().and_then(|i| table.data.get().get(i))
+    }
+
+    fn next(&mut self) {
+        match self.view {
+            View::Links => self.links.next(),
+            View::Upstreams => self.upstreams.next(),
+            View::Sets => self.sets.next(),
+        }
+    }
+
+    fn previous(&mut self) {
+        match self.view {
+            View::Links => self.links.previous(),
+            View::Upstreams => self.upstreams.previous(),
+            View::Sets => self.sets.previous(),
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.set_data(fetch(&*self.client));
+        for table in [&mut self.upstreams, &mut self.sets].iter_mut() {
+            let len = table.data.get().len();
+            if let Some(i) = table.state.selected() {
+                if i >= len {
+                    table.state.select(len.checked_sub(1));
+                }
+            }
+        }
+    }
+
+    fn set_data(&mut self, data: Result<FederationData, String>) {
+        match data {
+            Ok((links, upstreams, sets)) => {
+                self.links.data = DataContainer { entries: links };
+                self.upstreams.data = DataContainer { entries: upstreams };
+                self.sets.data = DataContainer { entries: sets };
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Opens the form for the current view, filled in
+    /// from `existing` when editing.
+    fn open_form(&mut self, existing: Option<Parameter>) {
+        let (title, name, vhost) = match &existing {
+            Some(p) => ("Edit", p.name.as_str(), p.vhost.as_str()),
+            None => ("New", "", "/"),
+        };
+        let value = existing.as_ref().map(|p| &p.value);
+        let text = |key: &str, default: &str| -> String {
+            match value.and_then(|v| v.get(key)) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Array(a)) => a
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(","),
+                Some(v) => v.to_string(),
+                None => default.to_string(),
+            }
+        };
+        let form = match self.view {
+            View::Links => return,
+            View::Upstreams => Form::new(&format!("{} upstream", title))
+                .field("Name", name)
+                .field("Vhost", vhost)
+                .field("URI", &text("uri", "amqp://"))
+                .field("Exchange (blank for same name)", &text("exchange", ""))
+                .field("Queue (blank for same name)", &text("queue", ""))
+                .field("Max hops", &text("max-hops", "1"))
+                .field("Prefetch count", &text("prefetch-count", "1000"))
+                .field("Ack mode", &text("ack-mode", "on-confirm")),
+            View::Sets => {
+                let members = value
+                    .and_then(|v| v.as_array())
+                    .map(|a| {
+                        a.iter()
+                            .filter_map(|m| m.get("upstream").and_then(|u| u.as_str()))
+                            .collect::<Vec<&str>>()
+                            .join(",")
+                    })
+                    .unwrap_or_default();
+                Form::new(&format!("{} upstream set", title))
+                    .field("Name", name)
+                    .field("Vhost", vhost)
+                    .field("Upstreams", &members)
+            }
+        };
+        self.form = Some((self.view, form));
+    }
+
+    /// Builds the parameter value from the form, or an
+    /// error describing what needs fixing.
+    fn form_value(view: View, form: &Form) -> Result<Value, String> {
+        let list = |s: &str| -> Vec<String> {
+            s.split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect()
+        };
+        match view {
+            View::Sets => {
+                let members = list(form.value(2));
+                if members.is_empty() {
+                    return Err("An upstream set needs at least one upstream".to_string());
+                }
+                Ok(Value::Array(
+                    members.iter().map(|m| json!({ "upstream": m })).collect(),
+                ))
+            }
+            _ => {
+                let uris = list(form.value(2));
+                let mut value = Map::new();
+                match uris.len() {
+                    0 => return Err("URI is required".to_string()),
+                    1 => value.insert("uri".to_string(), json!(uris[0])),
+                    _ => value.insert("uri".to_string(), json!(uris)),
+                };
+                for (i, key) in [(3, "exchange"), (4, "queue")].iter() {
+                    if !form.value(*i).is_empty() {
+                        value.insert(key.to_string(), json!(form.value(*i)));
+                    }
+                }
+                for (i, key) in [(5, "max-hops"), (6, "prefetch-count")].iter() {
+                    let n = form
+                        .value(*i)
+                        .parse::<u64>()
+                        .map_err(|_| format!("{} must be a number", key))?;
+                    value.insert(key.to_string(), json!(n));
+                }
+                value.insert("ack-mode".to_string(), json!(form.value(7)));
+                Ok(Value::Object(value))
+            }
+        }
+    }
+
+    /// Saves the form. Returns false if the form
+    /// should stay open so the input can be fixed.
+    fn submit_form(&mut self, view: View, form: &Form) -> bool {
+        if form.value(0).is_empty() {
+            self.notif = Some(Notification::new("Name is required".to_string()));
+            return false;
+        }
+        let value = match Self::form_value(view, form) {
+            Ok(v) => v,
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                return false;
+            }
+        };
+        let param = Parameter {
+            component: view.component().to_string(),
+            vhost: form.value(1).to_string(),
+            name: form.value(0).to_string(),
+            value,
+        };
+        match self.client.put_parameter(&param) {
+            Ok(_) => {
+                self.refresh();
+                self.notif = Some(Notification::new("Saved!".to_string()));
+                true
+            }
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                false
+            }
+        }
+    }
+}
+
+impl<M, B> Drawable<B> for FederationPane<'_, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn draw(&mut self, f: &mut Frame<B>, area: Rect) {
+        let rects = Layout::default()
+            .constraints([Constraint::Percentage(100)].as_ref())
+            .margin(1)
+            .split(area);
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let header_style = Style::default().fg(Color::Green);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(self.view.title());
+        if let Some(e) = &self.error {
+            draw_error(f, rects[0], self.view.title(), e);
+        } else if self.view == View::Links {
+            let header = Row::new(
+                FederationLink::headers()
+                    .iter()
+                    .map(|h| Cell::from(*h).style(header_style)),
+            )
+            .height(1)
+            .bottom_margin(1);
+            let rows = self.links.data.get().iter().map(|r| {
+                let style = if r.status == "running" {
+                    Style::default()
+                } else {
+                    Style::default().fg(Color::Red)
+                };
+                let cells = r.to_row().into_iter().map(Cell::from);
+                Row::new(cells).style(style).bottom_margin(1)
+            });
+            let t = Table::new(rows)
+                .header(header)
+                .block(block)
+                .highlight_style(selected_style)
+                .highlight_symbol(">> ")
+                .widths(&[
+                    Constraint::Percentage(15),
+                    Constraint::Percentage(10),
+                    Constraint::Percentage(10),
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(25),
+                ]);
+            f.render_stateful_widget(t, rects[0], &mut self.links.state);
+        } else {
+            let header = Row::new(
+                Parameter::headers()
+                    .iter()
+                    .map(|h| Cell::from(*h).style(header_style)),
+            )
+            .height(1)
+            .bottom_margin(1);
+            let table = match self.view {
+                View::Sets => &mut self.sets,
+                _ => &mut self.upstreams,
+            };
+            let rows = table.data.get().iter().map(|r| {
+                let cells = r.to_row().into_iter().map(Cell::from);
+                Row::new(cells).bottom_margin(1)
+            });
+            let t = Table::new(rows)
+                .header(header)
+                .block(block)
+                .highlight_style(selected_style)
+                .highlight_symbol(">> ")
+                .widths(&[
+                    Constraint::Percentage(20),
+                    Constraint::Percentage(10),
+                    Constraint::Percentage(70),
+                ]);
+            f.render_stateful_widget(t, rects[0], &mut table.state);
+        }
+        if self.should_confirm {
+            self.confirmation.draw(f, area);
+        }
+        if let Some((_, form)) = &self.form {
+            form.draw(f, area);
+        }
+        if let Some(n) = &self.notif {
+            n.draw(f, area);
+        }
+        if self.should_show_help {
+            Help::new(HELP).draw(f, area);
+        }
+    }
+}
+
+impl<'a, M, B> StatefulPane<B> for FederationPane<'a, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn handle_key(&mut self, key: Key) {
+        self.notif = None;
+        if let Some((view, mut form)) = self.form.take() {
+            let keep_open = match form.handle_key(key) {
+                FormAction::Submit => !self.submit_form(view, &form),
+                FormAction::Cancel => false,
+                FormAction::None => true,
+            };
+            if keep_open {
+                self.form = Some((view, form));
+            }
+            return;
+        }
+        match key {
+            Key::Char('j') => {
+                if self.should_confirm {
+                    self.confirmation.next();
+                } else {
+                    self.next();
+                }
+            }
+            Key::Char('k') => {
+                if self.should_confirm {
+                    self.confirmation.previous();
+                } else {
+                    self.previous();
+                }
+            }
+            Key::Char('v') if !self.should_confirm => {
+                self.view = self.view.next();
+            }
+            Key::Char('n') if self.view != View::Links => {
+                self.open_form(None);
+            }
+            Key::Char('e') if self.view != View::Links => {
+                if let Some(p) = self.selected().cloned() {
+                    self.open_form(Some(p));
+                }
+            }
+            Key::Char('d') if self.view != View::Links && self.selected().is_some() => {
+                self.should_confirm = true;
+            }
+            Key::Char('\n') if self.should_confirm => {
+                if self.confirmation.is_confirmed() {
+                    if let Some(p) = self.selected() {
+                        let msg =
+                            match self
+                                .client
+                                .delete_parameter(&p.component, &p.vhost, &p.name)
+                            {
+                                Ok(_) => "Deleted!".to_string(),
+                                Err(e) => e,
+                            };
+                        self.refresh();
+                        self.notif = Some(Notification::new(msg));
+                    }
+                }
+                self.confirmation.reset();
+                self.should_confirm = false;
+            }
+            Key::Char('?') => {
+                self.should_show_help = !self.should_show_help;
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self) {
+        if let Some(data) = self.data_chan.try_iter().next() {
+            self.set_data(data);
+        }
+    }
+
+    fn is_capturing_input(&self) -> bool {
+        self.form.is_some()
+    }
+}
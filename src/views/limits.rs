@@ -0,0 +1,311 @@
+use super::{draw_error, Drawable, StatefulPane};
+use crate::{
+    models::{QueueInfo, VhostLimit},
+    widgets::{
+        confirmation::ConfirmationBox,
+        form::{Form, FormAction},
+        help::Help,
+        notif::Notification,
+    },
+    DataContainer, Datatable, ManagementClient, Rowable,
+};
+
+use std::sync::{mpsc, Arc};
+
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Cell, Row, Table},
+    Frame,
+};
+
+const HELP: &str = "The Limits tab shows vhost limits such as \
+max-connections and max-queues along with current usage. Limits \
+over 75% used are yellow, over 90% red.
+
+Keys:
+  - h: previous tab
+  - l: next tab
+  - k: previous row
+  - j: next row
+  - n: set a new limit
+  - e: edit selected limit
+  - d: remove selected limit
+  - ?: close the help menu
+
+A value of -1 means unlimited.";
+
+/// Reads the pane's data, counting queues from `queues`, which
+/// the caller usually has at hand already.
+pub fn fetch<M: ManagementClient>(
+    client: &M,
+    queues: &[QueueInfo],
+) -> Result<Vec<VhostLimit>, String> {
+    Ok(VhostLimit::with_usage(
+        client.get_vhost_limits()?,
+        &client.get_connection_counts()?,
+        queues,
+    ))
+}
+
+const WARN_RATIO: f64 = 0.75;
+const CRIT_RATIO: f64 = 0.9;
+
+pub struct LimitsPane<'a, M>
+where
+    M: ManagementClient,
+{
+    table: Datatable<VhostLimit>,
+    confirmation: ConfirmationBox<'a>,
+    data_chan: mpsc::Receiver<Result<Vec<VhostLimit>, String>>,
+    client: Arc<M>,
+    /// Why the limits couldn't be fetched, if they couldn't.
+    error: Option<String>,
+    form: Option<Form>,
+    /// The (vhost, name) of the limit being edited, if
+    /// the open form is not creating a new one.
+    editing: Option<(String, String)>,
+    notif: Option<Notification>,
+    should_show_help: bool,
+    should_confirm: bool,
+}
+
+impl<'a, M> LimitsPane<'a, M>
+where
+    M: ManagementClient,
+{
+    pub fn new(client: Arc<M>, data_chan: mpsc::Receiver<Result<Vec<VhostLimit>, String>>) -> Self {
+        Self {
+            table: Datatable::default(),
+            confirmation: ConfirmationBox::default(),
+            data_chan,
+            client: Arc::clone(&client),
+            error: None,
+            form: None,
+            editing: None,
+            notif: None,
+            should_show_help: false,
+            should_confirm: false,
+        }
+    }
+
+    fn selected(&self) -> Option<&VhostLimit> {
+        self.table
+            .state
+            .selected()
+            .and_then(|i| self.table.data.get().get(i))
+    }
+
+    fn refresh(&mut self) {
+        let data = self
+            .client
+            .get_queues_info()
+            .and_then(|q| fetch(&*self.client, &q));
+        let data = match data {
+            Ok(d) => d,
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        };
+        self.error = None;
+        self.table.data.set(data);
+        let len = self.table.data.get().len();
+        if let Some(i) = self.table.state.selected() {
+            if i >= len {
+                self.table.state.select(len.checked_sub(1));
+            }
+        }
+    }
+
+    fn open_form(&mut self, existing: Option<&VhostLimit>) {
+        let form = match existing {
+            Some(l) => Form::new("Edit vhost limit")
+                .field("Vhost", &l.vhost)
+                .field("Limit (max-connections, max-queues)", &l.name)
+                .field("Value", &l.value.to_string()),
+            None => Form::new("New vhost limit")
+                .field("Vhost", "/")
+                .field("Limit (max-connections, max-queues)", "max-connections")
+                .field("Value", ""),
+        };
+        self.form = Some(form);
+        self.editing = existing.map(|l| (l.vhost.clone(), l.name.clone()));
+    }
+
+    /// Saves the form. Returns false if the form
+    /// should stay open so the input can be fixed.
+    fn submit_form(&mut self, form: &Form) -> bool {
+        let value = match form.value(2).parse::<i64>() {
+            Ok(v) => v,
+            Err(_) => {
+                self.notif = Some(Notification::new("Value must be a number".to_string()));
+                return false;
+            }
+        };
+        let (vhost, name) = (form.value(0), form.value(1));
+        if let Err(e) = self.client.put_vhost_limit(vhost, name, value) {
+            self.notif = Some(Notification::new(e));
+            return false;
+        }
+        // Moving or renaming a limit means the old one has
+        // to go, otherwise the vhost would keep both.
+        let mut res = Ok(());
+        if let Some((old_vhost, old_name)) = self.editing.take() {
+            if old_vhost != vhost || old_name != name {
+                res = self.client.delete_vhost_limit(&old_vhost, &old_name);
+            }
+        }
+        self.refresh();
+        self.notif = Some(Notification::new(match res {
+            Ok(_) => "Limit saved!".to_string(),
+            Err(e) => format!("Limit saved, but the old one is still set: {}", e),
+        }));
+        true
+    }
+}
+
+impl<M, B> Drawable<B> for LimitsPane<'_, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn draw(&mut self, f: &mut Frame<B>, area: Rect) {
+        let data = self.table.data.get();
+        let rects = Layout::default()
+            .constraints([Constraint::Percentage(100)].as_ref())
+            .margin(1)
+            .split(area);
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let normal_style = Style::default();
+        let header_literals = VhostLimit::headers();
+        let header_cells = header_literals
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Green)));
+        let header = Row::new(header_cells)
+            .style(normal_style)
+            .height(1)
+            .bottom_margin(1);
+        let rows = data.iter().map(|r| {
+            let style = match r.ratio() {
+                Some(ratio) if ratio >= CRIT_RATIO => Style::default().fg(Color::Red),
+                Some(ratio) if ratio >= WARN_RATIO => Style::default().fg(Color::Yellow),
+                _ => Style::default(),
+            };
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).style(style).bottom_margin(1)
+        });
+        let t = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title("Vhost Limits"))
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(30),
+                Constraint::Percentage(25),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+            ]);
+        match &self.error {
+            Some(e) => draw_error(f, rects[0], "Vhost Limits", e),
+            None => f.render_stateful_widget(t, rects[0], &mut self.table.state),
+        }
+        if self.should_confirm {
+            self.confirmation.draw(f, area);
+        }
+        if let Some(form) = &self.form {
+            form.draw(f, area);
+        }
+        if let Some(n) = &self.notif {
+            n.draw(f, area);
+        }
+        if self.should_show_help {
+            Help::new(HELP).draw(f, area);
+        }
+    }
+}
+
+impl<'a, M, B> StatefulPane<B> for LimitsPane<'a, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn handle_key(&mut self, key: Key) {
+        self.notif = None;
+        if let Some(mut form) = self.form.take() {
+            let keep_open = match form.handle_key(key) {
+                FormAction::Submit => !self.submit_form(&form),
+                FormAction::Cancel => false,
+                FormAction::None => true,
+            };
+            if keep_open {
+                self.form = Some(form);
+            }
+            return;
+        }
+        match key {
+            Key::Char('j') => {
+                if self.should_confirm {
+                    self.confirmation.next();
+                } else {
+                    self.table.next();
+                }
+            }
+            Key::Char('k') => {
+                if self.should_confirm {
+                    self.confirmation.previous();
+                } else {
+                    self.table.previous();
+                }
+            }
+            Key::Char('n') => {
+                self.open_form(None);
+            }
+            Key::Char('e') => {
+                if let Some(l) = self.selected().cloned() {
+                    self.open_form(Some(&l));
+                }
+            }
+            Key::Char('d') if self.selected().is_some() => {
+                self.should_confirm = true;
+            }
+            Key::Char('\n') if self.should_confirm => {
+                if self.confirmation.is_confirmed() {
+                    if let Some(l) = self.selected() {
+                        let msg = match self.client.delete_vhost_limit(&l.vhost, &l.name) {
+                            Ok(_) => "Limit removed!".to_string(),
+                            Err(e) => e,
+                        };
+                        self.refresh();
+                        self.notif = Some(Notification::new(msg));
+                    }
+                }
+                self.confirmation.reset();
+                self.should_confirm = false;
+            }
+            Key::Char('?') => {
+                self.should_show_help = !self.should_show_help;
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self) {
+        match self.data_chan.try_iter().next() {
+            Some(Ok(d)) => {
+                self.table.data = DataContainer { entries: d };
+                self.error = None;
+            }
+            Some(Err(e)) => self.error = Some(e),
+            None => {}
+        }
+    }
+
+    fn is_capturing_input(&self) -> bool {
+        self.form.is_some()
+    }
+}
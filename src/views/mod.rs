@@ -1,11 +1,19 @@
 pub mod exchange;
+pub mod federation;
+pub mod limits;
 pub mod overview;
+pub mod policies;
 pub mod queues;
+pub mod shovels;
+pub mod topology;
+pub mod users;
 
 use termion::event::Key;
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    widgets::{Block, Borders, Paragraph, Wrap},
     Frame,
 };
 
@@ -27,6 +35,13 @@ where
 {
     fn handle_key(&mut self, key: Key);
     fn update(&mut self);
+
+    /// Whether the pane currently wants every key press,
+    /// e.g. while a text form is open. App wide keys such
+    /// as tab switching and quitting are suspended meanwhile.
+    fn is_capturing_input(&self) -> bool {
+        false
+    }
 }
 
 /// helper function to create a centered rect using up
@@ -56,3 +71,13 @@ pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         )
         .split(popup_layout[1])[1]
 }
+
+/// Draws why a pane's data couldn't be fetched, in place of
+/// the table it would have filled.
+pub fn draw_error<B: Backend>(f: &mut Frame<B>, area: Rect, title: &str, error: &str) {
+    let p = Paragraph::new(error)
+        .style(Style::default().fg(Color::Red))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: true });
+    f.render_widget(p, area);
+}
@@ -1,22 +1,31 @@
-use super::{Drawable, StatefulPane};
+use super::{centered_rect, Drawable, StatefulPane};
 use crate::{
+    definitions::{self, DefinitionItem, ImportPlan},
     models::Overview,
     widgets::{
         chart::{ChartData, RChart},
+        confirmation::ConfirmationBox,
+        files::FileNavigator,
+        form::{Form, FormAction},
         help::Help,
+        notif::Notification,
     },
-    ManagementClient,
+    Datatable, ManagementClient, Rowable,
 };
 
-use std::sync::{mpsc, Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+};
 
+use serde_json::Value;
 use termion::event::Key;
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem},
+    widgets::{Block, Borders, Cell, Clear, List, ListItem, Row, Table},
     Frame,
 };
 
@@ -26,10 +35,41 @@ be specific to the tab you are in.
 
 The overview pane shows high level throughput analytics.
 
+Definitions can be exported to and imported from JSON files.
+
 Keys:
   - h: previous tab
   - l: next tab
-  - ?: close the help menu";
+  - e: export definitions
+  - i: import definitions
+  - ?: close the help menu
+
+While picking a file, return opens a directory or picks \
+the file to import, backspace goes up a directory and s \
+saves the export under the name you gave into the current \
+directory, asking first if that file already exists. Imports show what would be created before \
+anything is changed.";
+
+#[derive(Clone, Copy)]
+enum FormKind {
+    Export,
+    Import,
+}
+
+/// Which way definitions are moving, along with the
+/// vhost they are scoped to, if any.
+enum Transfer {
+    Export { vhost: Option<String>, file: String },
+    Import { vhost: Option<String> },
+}
+
+/// An import that has been planned but not applied yet.
+struct PendingImport {
+    vhost: Option<String>,
+    definitions: Value,
+    existing: usize,
+    table: Datatable<DefinitionItem>,
+}
 
 #[derive(Default)]
 struct OverviewData {
@@ -40,43 +80,231 @@ struct OverviewData {
     disk_write_rate: ChartData,
 }
 
-pub struct OverviewPane {
+pub struct OverviewPane<M>
+where
+    M: ManagementClient,
+{
     data: OverviewData,
     data_chan: mpsc::Receiver<Overview>,
+    client: Arc<M>,
     counter: f64,
+    form: Option<(FormKind, Form)>,
+    /// Set while a file is being picked for a transfer.
+    transfer: Option<(Transfer, FileNavigator)>,
+    /// Set while confirming an export to a file that already exists.
+    overwrite: Option<PathBuf>,
+    confirmation: ConfirmationBox<'static>,
+    pending_import: Option<PendingImport>,
+    notif: Option<Notification>,
     should_show_help: bool,
 }
 
-impl OverviewPane {
-    pub fn new<M>(client: Arc<M>, data_chan: mpsc::Receiver<Overview>) -> Self
-    where
-        M: ManagementClient,
-    {
-        let data = client.get_overview();
-        let mut overall = ChartData::default();
-        overall.push(data.queue_totals.messages);
-        let mut ready = ChartData::default();
-        ready.push(data.queue_totals.messages_ready);
-        let mut unacked = ChartData::default();
-        unacked.push(data.queue_totals.messages_unacked);
-        let mut disk_read_rate = ChartData::default();
-        disk_read_rate.push(data.message_stats.disk_reads_details.rate);
-        let mut disk_write_rate = ChartData::default();
-        disk_write_rate.push(data.message_stats.disk_writes_details.rate);
-        Self {
+impl<M> OverviewPane<M>
+where
+    M: ManagementClient,
+{
+    pub fn new(client: Arc<M>, data_chan: mpsc::Receiver<Overview>) -> Self {
+        let mut pane = Self {
             counter: 0.,
             data_chan,
-            data: OverviewData {
-                overall,
-                ready,
-                unacked,
-                disk_read_rate,
-                disk_write_rate,
-            },
+            client: Arc::clone(&client),
+            data: OverviewData::default(),
+            form: None,
+            transfer: None,
+            overwrite: None,
+            confirmation: ConfirmationBox::default(),
+            pending_import: None,
+            notif: None,
             should_show_help: false,
+        };
+        // Charts start empty if this fails, the data thread fills them in.
+        if let Ok(data) = client.get_overview() {
+            pane.push(data);
+        }
+        pane
+    }
+
+    fn push(&mut self, data: Overview) {
+        self.data.ready.push(data.queue_totals.messages_ready);
+        self.data.overall.push(data.queue_totals.messages);
+        self.data.unacked.push(data.queue_totals.messages_unacked);
+        self.data
+            .disk_write_rate
+            .push(data.message_stats.disk_writes_details.rate);
+        self.data
+            .disk_read_rate
+            .push(data.message_stats.disk_reads_details.rate);
+    }
+
+    fn open_export_form(&mut self) {
+        let form = Form::new("Export definitions")
+            .field("Vhost (empty for all)", "")
+            .field("File name", "definitions.json");
+        self.form = Some((FormKind::Export, form));
+    }
+
+    fn open_import_form(&mut self) {
+        let form = Form::new("Import definitions").field("Vhost (empty for all)", "");
+        self.form = Some((FormKind::Import, form));
+    }
+
+    /// Moves on from the form to picking a file.
+    fn submit_form(&mut self, kind: FormKind, form: &Form) -> bool {
+        let vhost = Some(form.value(0).to_string()).filter(|v| !v.is_empty());
+        let transfer = match kind {
+            FormKind::Export if form.value(1).is_empty() => {
+                self.notif = Some(Notification::new("File name is required".to_string()));
+                return false;
+            }
+            FormKind::Export => Transfer::Export {
+                vhost,
+                file: form.value(1).to_string(),
+            },
+            FormKind::Import => Transfer::Import { vhost },
+        };
+        self.transfer = Some((transfer, FileNavigator::default()));
+        true
+    }
+
+    fn export(&mut self, vhost: Option<&str>, path: &Path) {
+        let msg = match definitions::export(self.client.as_ref(), vhost, Some(path)) {
+            Ok(n) => format!("Exported {} objects!", n),
+            Err(e) => e,
+        };
+        self.transfer = None;
+        self.notif = Some(Notification::new(msg));
+    }
+
+    /// Reads the file and works out what it would create,
+    /// leaving the actual import for confirmation.
+    fn plan_import(&mut self, vhost: Option<String>, path: &Path) {
+        let res = definitions::read(path).and_then(|defs| {
+            definitions::import(self.client.as_ref(), vhost.as_deref(), &defs, true)
+                .map(|plan| (defs, plan))
+        });
+        self.transfer = None;
+        match res {
+            Ok((definitions, ImportPlan { created, existing })) => {
+                let mut table = Datatable::new(created);
+                table.next();
+                self.pending_import = Some(PendingImport {
+                    vhost,
+                    definitions,
+                    existing,
+                    table,
+                });
+            }
+            Err(e) => self.notif = Some(Notification::new(e)),
+        }
+    }
+
+    fn apply_import(&mut self) {
+        if let Some(p) = self.pending_import.take() {
+            let res = definitions::import(
+                self.client.as_ref(),
+                p.vhost.as_deref(),
+                &p.definitions,
+                false,
+            );
+            let msg = match res {
+                Ok(_) => "Definitions imported!".to_string(),
+                Err(e) => e,
+            };
+            self.notif = Some(Notification::new(msg));
+        }
+    }
+
+    fn handle_transfer_key(&mut self, key: Key) {
+        let (transfer, mut explorer) = match self.transfer.take() {
+            Some(t) => t,
+            None => return,
+        };
+        match key {
+            Key::Char('j') => explorer.next(),
+            Key::Char('k') => explorer.previous(),
+            Key::Backspace => explorer.select_parent(),
+            Key::Esc => return,
+            Key::Char('s') => {
+                if let Transfer::Export { vhost, file } = &transfer {
+                    let path = explorer.root().join(file);
+                    if !path.exists() {
+                        self.export(vhost.as_deref(), &path);
+                        return;
+                    }
+                    self.confirmation.reset();
+                    self.overwrite = Some(path);
+                }
+            }
+            Key::Char('\n') => {
+                // Exports only go to the name that was typed in, so
+                // picking a file never overwrites it.
+                if let Some(path) = explorer.select() {
+                    if let Transfer::Import { vhost } = transfer {
+                        self.plan_import(vhost, &path);
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.transfer = Some((transfer, explorer));
+    }
+
+    fn handle_overwrite_key(&mut self, key: Key) {
+        match key {
+            Key::Char('j') => self.confirmation.next(),
+            Key::Char('k') => self.confirmation.previous(),
+            Key::Esc => self.overwrite = None,
+            Key::Char('\n') => {
+                let path = self.overwrite.take();
+                if !self.confirmation.is_confirmed() {
+                    return;
+                }
+                if let (Some(path), Some((Transfer::Export { vhost, .. }, _))) =
+                    (path, self.transfer.take())
+                {
+                    self.export(vhost.as_deref(), &path);
+                }
+            }
+            _ => {}
         }
     }
 
+    fn draw_pending_import<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let pending = match &mut self.pending_import {
+            Some(p) => p,
+            None => return,
+        };
+        let header_literals = DefinitionItem::headers();
+        let header_cells = header_literals
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow)));
+        let header = Row::new(header_cells).height(1).bottom_margin(1);
+        let rows = pending.table.data.get().iter().map(|r| {
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells)
+        });
+        let title = format!(
+            "Import: {} new, {} existing - return: apply, esc: cancel",
+            pending.table.data.get().len(),
+            pending.existing
+        );
+        let t = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(20),
+                Constraint::Percentage(20),
+                Constraint::Percentage(60),
+            ]);
+        let pop_area = centered_rect(70, 70, area);
+        f.render_widget(Clear, pop_area);
+        f.render_stateful_widget(t, pop_area, &mut pending.table.state);
+    }
+
     fn draw_messages_panel<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
         let datasets = [&self.data.overall, &self.data.ready, &self.data.unacked];
         let colors = [Color::Yellow, Color::Cyan, Color::Red];
@@ -138,8 +366,9 @@ impl OverviewPane {
     }
 }
 
-impl<B> Drawable<B> for OverviewPane
+impl<M, B> Drawable<B> for OverviewPane<M>
 where
+    M: ManagementClient,
     B: Backend,
 {
     fn draw(&mut self, f: &mut Frame<B>, area: Rect) {
@@ -159,6 +388,19 @@ where
         self.draw_message_list(f, count_chunks[1]);
         self.draw_message_rates_panel(f, rate_chunks[0]);
         self.draw_message_rates_list(f, rate_chunks[1]);
+        if let Some((_, explorer)) = &mut self.transfer {
+            explorer.draw(f, area);
+        }
+        if self.overwrite.is_some() {
+            self.confirmation.draw(f, area);
+        }
+        self.draw_pending_import(f, area);
+        if let Some((_, form)) = &self.form {
+            form.draw(f, area);
+        }
+        if let Some(n) = &self.notif {
+            n.draw(f, area);
+        }
         if self.should_show_help {
             let help = Help::new(HELP);
             help.draw(f, area);
@@ -166,12 +408,49 @@ where
     }
 }
 
-impl<B> StatefulPane<B> for OverviewPane
+impl<M, B> StatefulPane<B> for OverviewPane<M>
 where
+    M: ManagementClient,
     B: Backend,
 {
     fn handle_key(&mut self, key: Key) {
+        self.notif = None;
+        if let Some((kind, mut form)) = self.form.take() {
+            let keep_open = match form.handle_key(key) {
+                FormAction::Submit => !self.submit_form(kind, &form),
+                FormAction::Cancel => false,
+                FormAction::None => true,
+            };
+            if keep_open {
+                self.form = Some((kind, form));
+            }
+            return;
+        }
+        if self.overwrite.is_some() {
+            self.handle_overwrite_key(key);
+            return;
+        }
+        if self.transfer.is_some() {
+            self.handle_transfer_key(key);
+            return;
+        }
+        if let Some(p) = &mut self.pending_import {
+            match key {
+                Key::Char('j') => p.table.next(),
+                Key::Char('k') => p.table.previous(),
+                Key::Char('\n') => self.apply_import(),
+                Key::Esc => self.pending_import = None,
+                _ => {}
+            }
+            return;
+        }
         match key {
+            Key::Char('e') => {
+                self.open_export_form();
+            }
+            Key::Char('i') => {
+                self.open_import_form();
+            }
             Key::Char('?') => {
                 self.should_show_help = !self.should_show_help;
             }
@@ -182,15 +461,11 @@ where
     fn update(&mut self) {
         if let Some(update) = self.data_chan.try_iter().next() {
             self.counter += 1.0;
-            self.data.ready.push(update.queue_totals.messages_ready);
-            self.data.overall.push(update.queue_totals.messages);
-            self.data.unacked.push(update.queue_totals.messages_unacked);
-            self.data
-                .disk_write_rate
-                .push(update.message_stats.disk_writes_details.rate);
-            self.data
-                .disk_read_rate
-                .push(update.message_stats.disk_reads_details.rate);
+            self.push(update);
         }
     }
+
+    fn is_capturing_input(&self) -> bool {
+        self.form.is_some()
+    }
 }
@@ -0,0 +1,495 @@
+use super::{centered_rect, draw_error, Drawable, StatefulPane};
+use crate::{
+    models::{Policy, PolicyMatch},
+    widgets::{
+        confirmation::ConfirmationBox,
+        form::{Form, FormAction},
+        help::Help,
+        notif::Notification,
+    },
+    DataContainer, Datatable, ManagementClient, Rowable,
+};
+
+use std::{
+    collections::BTreeMap,
+    sync::{mpsc, Arc},
+};
+
+use serde_json::Value;
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Cell, Clear, Row, Table},
+    Frame,
+};
+
+const HELP: &str = "The Policies tab lists policies across all \
+vhosts and lets you manage them. Operator policies, which cap \
+queue settings regardless of user policies, live in a second view.
+
+Keys:
+  - h: previous tab
+  - l: next tab
+  - k: previous row
+  - j: next row
+  - o: switch between policies and operator policies
+  - n: create a new policy
+  - e: edit selected policy
+  - d: delete selected policy
+  - return: open/close preview of what the selected policy matches
+  - ?: close the help menu
+
+The definition is entered as a JSON object, e.g. \
+{\"max-length\": 1000}.";
+
+/// Policies and operator policies, each of which may have
+/// failed to load on its own.
+pub type PoliciesData = (Result<Vec<Policy>, String>, Result<Vec<Policy>, String>);
+
+/// Reads the pane's data. Listing policies needs the policymaker
+/// tag and operator policies the administrator tag, so a
+/// policymaker still gets their policies when the other fails.
+pub fn fetch<M: ManagementClient>(client: &M) -> PoliciesData {
+    (client.get_policies(), client.get_operator_policies())
+}
+
+const FORM_NAME: usize = 0;
+const FORM_VHOST: usize = 1;
+const FORM_PATTERN: usize = 2;
+const FORM_APPLY_TO: usize = 3;
+const FORM_PRIORITY: usize = 4;
+const FORM_DEFINITION: usize = 5;
+
+pub struct PoliciesPane<'a, M>
+where
+    M: ManagementClient,
+{
+    table: Datatable<Policy>,
+    operator_table: Datatable<Policy>,
+    preview_table: Datatable<PolicyMatch>,
+    confirmation: ConfirmationBox<'a>,
+    data_chan: mpsc::Receiver<PoliciesData>,
+    client: Arc<M>,
+    /// Why the policies couldn't be fetched, if they couldn't.
+    error: Option<String>,
+    /// Same for operator policies.
+    operator_error: Option<String>,
+    form: Option<Form>,
+    /// The (vhost, name) of the policy being edited, if
+    /// the open form is not creating a new one.
+    editing: Option<(String, String)>,
+    notif: Option<Notification>,
+    should_show_help: bool,
+    should_confirm: bool,
+    should_draw_preview: bool,
+    should_show_operator: bool,
+}
+
+impl<'a, M> PoliciesPane<'a, M>
+where
+    M: ManagementClient,
+{
+    pub fn new(client: Arc<M>, data_chan: mpsc::Receiver<PoliciesData>) -> Self {
+        Self {
+            table: Datatable::default(),
+            operator_table: Datatable::default(),
+            preview_table: Datatable::default(),
+            confirmation: ConfirmationBox::default(),
+            data_chan,
+            client: Arc::clone(&client),
+            error: None,
+            operator_error: None,
+            form: None,
+            editing: None,
+            notif: None,
+            should_show_help: false,
+            should_confirm: false,
+            should_draw_preview: false,
+            should_show_operator: false,
+        }
+    }
+
+    /// The table for whichever kind of policy is being shown.
+    fn active(&self) -> &Datatable<Policy> {
+        if self.should_show_operator {
+            &self.operator_table
+        } else {
+            &self.table
+        }
+    }
+
+    fn active_mut(&mut self) -> &mut Datatable<Policy> {
+        if self.should_show_operator {
+            &mut self.operator_table
+        } else {
+            &mut self.table
+        }
+    }
+
+    /// Why the kind of policy being shown couldn't be fetched.
+    fn active_error(&mut self) -> &mut Option<String> {
+        if self.should_show_operator {
+            &mut self.operator_error
+        } else {
+            &mut self.error
+        }
+    }
+
+    fn selected(&self) -> Option<&Policy> {
+        let table = self.active();
+        table.state.selected().and_then(|i| table.data.get().get(i))
+    }
+
+    fn open_form(&mut self, existing: Option<Policy>) {
+        let kind = if self.should_show_operator {
+            "operator policy"
+        } else {
+            "policy"
+        };
+        let form = match &existing {
+            Some(p) => Form::new(&format!("Edit {}", kind))
+                .field("Name", &p.name)
+                .field("Vhost", &p.vhost)
+                .field("Pattern", &p.pattern)
+                .field("Apply to (queues, exchanges, all)", &p.apply_to)
+                .field("Priority", &p.priority.to_string())
+                .field(
+                    "Definition (JSON)",
+                    &serde_json::to_string(&p.definition).unwrap_or_default(),
+                ),
+            None => Form::new(&format!("New {}", kind))
+                .field("Name", "")
+                .field("Vhost", "/")
+                .field("Pattern", "")
+                .field(
+                    "Apply to (queues, exchanges, all)",
+                    // Operator policies can only target queues.
+                    if self.should_show_operator {
+                        "queues"
+                    } else {
+                        "all"
+                    },
+                )
+                .field("Priority", "0")
+                .field("Definition (JSON)", "{}"),
+        };
+        self.editing = existing.map(|p| (p.vhost, p.name));
+        self.form = Some(form);
+    }
+
+    fn policy_from_form(form: &Form) -> Result<Policy, String> {
+        let priority = form
+            .value(FORM_PRIORITY)
+            .parse::<i64>()
+            .map_err(|_| "Priority must be a number".to_string())?;
+        let definition =
+            serde_json::from_str::<BTreeMap<String, Value>>(form.value(FORM_DEFINITION))
+                .map_err(|_| "Definition must be a JSON object".to_string())?;
+        if form.value(FORM_NAME).is_empty() {
+            return Err("Name is required".to_string());
+        }
+        Ok(Policy {
+            vhost: form.value(FORM_VHOST).to_string(),
+            name: form.value(FORM_NAME).to_string(),
+            pattern: form.value(FORM_PATTERN).to_string(),
+            apply_to: form.value(FORM_APPLY_TO).to_string(),
+            definition,
+            priority,
+        })
+    }
+
+    /// Saves the form. Returns false if the form
+    /// should stay open so the input can be fixed.
+    fn submit_form(&mut self, form: &Form) -> bool {
+        let policy = match Self::policy_from_form(form) {
+            Ok(p) => p,
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                return false;
+            }
+        };
+        let res = if self.should_show_operator {
+            self.client.put_operator_policy(&policy)
+        } else {
+            self.client.put_policy(&policy)
+        };
+        if let Err(e) = res {
+            self.notif = Some(Notification::new(e));
+            return false;
+        }
+        // Renaming or moving a policy means the old one
+        // has to go, otherwise both would apply.
+        if let Some((vhost, name)) = self.editing.take() {
+            if vhost != policy.vhost || name != policy.name {
+                let _ = self.delete(&vhost, &name);
+            }
+        }
+        self.refresh();
+        self.notif = Some(Notification::new("Policy saved!".to_string()));
+        true
+    }
+
+    fn delete(&self, vhost: &str, name: &str) -> Result<(), String> {
+        if self.should_show_operator {
+            self.client.delete_operator_policy(vhost, name)
+        } else {
+            self.client.delete_policy(vhost, name)
+        }
+    }
+
+    fn refresh(&mut self) {
+        let data = if self.should_show_operator {
+            self.client.get_operator_policies()
+        } else {
+            self.client.get_policies()
+        };
+        let data = match data {
+            Ok(d) => d,
+            Err(e) => {
+                *self.active_error() = Some(e);
+                return;
+            }
+        };
+        *self.active_error() = None;
+        let table = self.active_mut();
+        table.data.set(data);
+        let len = table.data.get().len();
+        if let Some(i) = table.state.selected() {
+            if i >= len {
+                table.state.select(len.checked_sub(1));
+            }
+        }
+    }
+
+    /// Works out what the selected policy matches. Returns
+    /// whether there is anything to show.
+    fn fetch_preview(&mut self) -> bool {
+        if self.selected().is_none() {
+            return false;
+        }
+        let objects = self
+            .client
+            .get_queues_info()
+            .and_then(|q| Ok((q, self.client.get_exchange_overview()?)));
+        let (queues, exchanges) = match objects {
+            Ok(o) => o,
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                return false;
+            }
+        };
+        if let Some(policy) = self.selected() {
+            let matches =
+                PolicyMatch::preview(policy, self.active().data.get(), &queues, &exchanges);
+            self.preview_table = Datatable::<PolicyMatch>::new(matches);
+        }
+        true
+    }
+
+    fn draw_preview<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let data = self.preview_table.data.get();
+        let header_lits = PolicyMatch::headers();
+        let header_cells = header_lits
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow)));
+        let header = Row::new(header_cells)
+            .style(Style::default())
+            .height(1)
+            .bottom_margin(1);
+        let rows = data.iter().map(|r| {
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).bottom_margin(1)
+        });
+        let title = format!("Matches ({})", data.len());
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let t = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(15),
+                Constraint::Percentage(45),
+                Constraint::Percentage(40),
+            ]);
+        let pop_area = centered_rect(60, 50, area);
+        f.render_widget(Clear, pop_area);
+        f.render_stateful_widget(t, pop_area, &mut self.preview_table.state);
+    }
+}
+
+impl<M, B> Drawable<B> for PoliciesPane<'_, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn draw(&mut self, f: &mut Frame<B>, area: Rect) {
+        let title = if self.should_show_operator {
+            "Operator Policies"
+        } else {
+            "Policies"
+        };
+        let data = self.active().data.get();
+        let rects = Layout::default()
+            .constraints([Constraint::Percentage(100)].as_ref())
+            .margin(1)
+            .split(area);
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let normal_style = Style::default();
+        let header_literals = Policy::headers();
+        let header_cells = header_literals
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Green)));
+        let header = Row::new(header_cells)
+            .style(normal_style)
+            .height(1)
+            .bottom_margin(1);
+        let rows = data.iter().map(|r| {
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).bottom_margin(1)
+        });
+        let t = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(15),
+                Constraint::Percentage(10),
+                Constraint::Percentage(15),
+                Constraint::Percentage(10),
+                Constraint::Percentage(10),
+                Constraint::Percentage(40),
+            ]);
+        let (state, error) = if self.should_show_operator {
+            (&mut self.operator_table.state, &self.operator_error)
+        } else {
+            (&mut self.table.state, &self.error)
+        };
+        match error {
+            Some(e) => draw_error(f, rects[0], title, e),
+            None => f.render_stateful_widget(t, rects[0], state),
+        }
+        if self.should_draw_preview {
+            self.draw_preview(f, area);
+        }
+        if self.should_confirm {
+            self.confirmation.draw(f, area);
+        }
+        if let Some(form) = &self.form {
+            form.draw(f, area);
+        }
+        if let Some(n) = &self.notif {
+            n.draw(f, area);
+        }
+        if self.should_show_help {
+            Help::new(HELP).draw(f, area);
+        }
+    }
+}
+
+impl<'a, M, B> StatefulPane<B> for PoliciesPane<'a, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn handle_key(&mut self, key: Key) {
+        self.notif = None;
+        if let Some(mut form) = self.form.take() {
+            let keep_open = match form.handle_key(key) {
+                FormAction::Submit => !self.submit_form(&form),
+                FormAction::Cancel => false,
+                FormAction::None => true,
+            };
+            if keep_open {
+                self.form = Some(form);
+            }
+            return;
+        }
+        match key {
+            Key::Char('j') => {
+                if self.should_confirm {
+                    self.confirmation.next();
+                } else if self.should_draw_preview {
+                    self.preview_table.next();
+                } else {
+                    self.active_mut().next();
+                }
+            }
+            Key::Char('k') => {
+                if self.should_confirm {
+                    self.confirmation.previous();
+                } else if self.should_draw_preview {
+                    self.preview_table.previous();
+                } else {
+                    self.active_mut().previous();
+                }
+            }
+            Key::Char('o') => {
+                self.should_draw_preview = false;
+                self.should_show_operator = !self.should_show_operator;
+            }
+            Key::Char('n') => {
+                self.open_form(None);
+            }
+            Key::Char('e') => {
+                if let Some(p) = self.selected().cloned() {
+                    self.open_form(Some(p));
+                }
+            }
+            Key::Char('d') if self.selected().is_some() => {
+                self.should_confirm = true;
+            }
+            Key::Char('\n') => {
+                if self.should_confirm {
+                    if self.confirmation.is_confirmed() {
+                        if let Some(p) = self.selected() {
+                            let res = self.delete(&p.vhost, &p.name);
+                            let msg = match res {
+                                Ok(_) => "Policy deleted!".to_string(),
+                                Err(e) => e,
+                            };
+                            self.refresh();
+                            self.notif = Some(Notification::new(msg));
+                        }
+                    }
+                    self.confirmation.reset();
+                    self.should_confirm = false;
+                } else {
+                    self.should_draw_preview = !self.should_draw_preview && self.fetch_preview();
+                }
+            }
+            Key::Char('?') => {
+                self.should_show_help = !self.should_show_help;
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self) {
+        if let Some((policies, operator_policies)) = self.data_chan.try_iter().next() {
+            match policies {
+                Ok(entries) => {
+                    self.table.data = DataContainer { entries };
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(e),
+            }
+            match operator_policies {
+                Ok(entries) => {
+                    self.operator_table.data = DataContainer { entries };
+                    self.operator_error = None;
+                }
+                Err(e) => self.operator_error = Some(e),
+            }
+        }
+    }
+
+    fn is_capturing_input(&self) -> bool {
+        self.form.is_some()
+    }
+}
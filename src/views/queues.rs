@@ -1,8 +1,13 @@
-use super::{Drawable, StatefulPane};
+use super::{centered_rect, Drawable, StatefulPane};
 use crate::{
-    models::QueueInfo,
+    models::{local_uri, InboundBinding, QueueInfo, ShovelDefinition},
     widgets::{
-        confirmation::ConfirmationBox, files::FileNavigator, help::Help, notif::Notification,
+        confirmation::ConfirmationBox,
+        files::FileNavigator,
+        form::{Form, FormAction},
+        help::Help,
+        notif::Notification,
+        policy::PolicyPopup,
     },
     DataContainer, Datatable, ManagementClient, Rowable,
 };
@@ -18,7 +23,7 @@ use tui::{
     backend::Backend,
     layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Cell, Row, Table},
+    widgets::{Block, Borders, Cell, Clear, Row, Table},
     Frame,
 };
 
@@ -33,6 +38,9 @@ Keys:
   - p: drop message into queue from clipboard
   - ctrl + p: pop message from queue onto clipboard
   - d: purge selected queue
+  - b: open/close bindings routing into selected queue
+  - i: open/close effective policy breakdown for selected queue
+  - s: shovel messages of selected queue to another queue
   - return: select
   - f: open/close file explorer
   - backspace: go to parent in file explorer
@@ -43,6 +51,8 @@ where
     M: ManagementClient,
 {
     table: Datatable<QueueInfo>,
+    bindings_table: Datatable<InboundBinding>,
+    policy: PolicyPopup,
     confirmation: ConfirmationBox<'a>,
     data_chan: mpsc::Receiver<Vec<QueueInfo>>,
     explorer: FileNavigator,
@@ -52,10 +62,14 @@ where
     // for an indv pane to have a clipboard context
     // when there is only 1 system clipboard..
     clipboard: ClipboardContext,
+    /// Shovel form, along with the queue being shoveled.
+    shovel_form: Option<(QueueInfo, Form)>,
     notif: Option<Notification>,
     should_show_help: bool,
     should_confirm: bool,
     should_open_files: bool,
+    should_draw_bindings: bool,
+    should_draw_policy: bool,
 }
 
 impl<'a, M> QueuesPane<'a, M>
@@ -63,10 +77,13 @@ where
     M: ManagementClient,
 {
     pub fn new(client: Arc<M>, data_chan: mpsc::Receiver<Vec<QueueInfo>>) -> Self {
-        let data = client.get_queues_info();
+        // Left empty if this fails, the data thread fills it in.
+        let data = client.get_queues_info().unwrap_or_default();
         let table = Datatable::<QueueInfo>::new(data);
         Self {
             table,
+            bindings_table: Datatable::default(),
+            policy: PolicyPopup::default(),
             confirmation: ConfirmationBox::default(),
             explorer: FileNavigator::default(),
             notif: None,
@@ -74,11 +91,134 @@ where
             client: Arc::clone(&client),
             // TODO handle unable to make clipboard?
             clipboard: ClipboardProvider::new().unwrap(),
+            shovel_form: None,
             should_show_help: false,
             should_confirm: false,
             should_open_files: false,
+            should_draw_bindings: false,
+            should_draw_policy: false,
         }
     }
+
+    fn open_shovel_form(&mut self) {
+        if let Some(i) = self.table.state.selected() {
+            let info = self.table.data.get()[i].clone();
+            let form = Form::new(&format!("Shovel {} to", info.name))
+                .field("Shovel name", &format!("{}-shovel", info.name))
+                .field("Destination URI", &local_uri(&info.vhost))
+                .field("Destination queue", "")
+                .field("Delete after (never, queue-length)", "never");
+            self.shovel_form = Some((info, form));
+        }
+    }
+
+    /// Creates the shovel. Returns false if the form
+    /// should stay open so the input can be fixed.
+    fn submit_shovel_form(&mut self, info: &QueueInfo, form: &Form) -> bool {
+        if form.value(0).is_empty() || form.value(2).is_empty() {
+            self.notif = Some(Notification::new(
+                "Name and destination queue are required".to_string(),
+            ));
+            return false;
+        }
+        let mut def = ShovelDefinition::queue_to_queue(
+            &local_uri(&info.vhost),
+            &info.name,
+            form.value(1),
+            form.value(2),
+        );
+        def.src_delete_after = match form.value(3) {
+            "" | "never" => None,
+            other => Some(other.to_string()),
+        };
+        let param = def.into_parameter(&info.vhost, form.value(0));
+        match self.client.put_parameter(&param) {
+            Ok(_) => {
+                self.notif = Some(Notification::new("Shovel created!".to_string()));
+                true
+            }
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                false
+            }
+        }
+    }
+
+    fn fetch_policy_details(&mut self) {
+        if let Some(i) = self.table.state.selected() {
+            let info = &self.table.data.get()[i];
+            let policies = self
+                .client
+                .get_policies()
+                .and_then(|p| Ok((p, self.client.get_operator_policies()?)));
+            self.policy = match policies {
+                Ok((p, op)) => PolicyPopup::new(info.policy_details(&p, &op)),
+                Err(e) => PolicyPopup::failed(e),
+            };
+        }
+    }
+
+    /// Fetches every binding that routes into the selected
+    /// queue, resolving the type of each source exchange.
+    /// Returns whether there is anything to show.
+    fn fetch_bindings(&mut self) -> bool {
+        if let Some(i) = self.table.state.selected() {
+            let info = &self.table.data.get()[i];
+            let fetched = self
+                .client
+                .get_exchange_overview()
+                .and_then(|e| Ok((e, self.client.get_queue_bindings(info)?)));
+            let (exchanges, bindings) = match fetched {
+                Ok(f) => f,
+                Err(e) => {
+                    self.notif = Some(Notification::new(e));
+                    return false;
+                }
+            };
+            let data = bindings
+                .into_iter()
+                .map(|b| InboundBinding::new(b, &exchanges))
+                .collect();
+            self.bindings_table = Datatable::<InboundBinding>::new(data);
+            return true;
+        }
+        false
+    }
+
+    fn draw_bindings<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let data = self.bindings_table.data.get();
+        let header_lits = InboundBinding::headers();
+        let header_cells = header_lits
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow)));
+        let header = Row::new(header_cells)
+            .style(Style::default())
+            .height(1)
+            .bottom_margin(1);
+        let rows = data.iter().map(|r| {
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).bottom_margin(1)
+        });
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let t = Table::new(rows)
+            .header(header)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Inbound Bindings"),
+            )
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(40),
+                Constraint::Percentage(20),
+                Constraint::Percentage(40),
+            ]);
+        let pop_area = centered_rect(60, 50, area);
+        f.render_widget(Clear, pop_area);
+        f.render_stateful_widget(t, pop_area, &mut self.bindings_table.state);
+    }
 }
 
 impl<M, B> Drawable<B> for QueuesPane<'_, M>
@@ -113,17 +253,24 @@ where
             .highlight_style(selected_style)
             .highlight_symbol(">> ")
             .widths(&[
-                Constraint::Percentage(20),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
-                Constraint::Percentage(10),
+                Constraint::Percentage(18),
+                Constraint::Percentage(8),
+                Constraint::Percentage(8),
+                Constraint::Percentage(12),
+                Constraint::Percentage(8),
+                Constraint::Percentage(8),
+                Constraint::Percentage(8),
                 Constraint::Percentage(10),
                 Constraint::Percentage(10),
                 Constraint::Percentage(10),
             ]);
         f.render_stateful_widget(t, rects[0], &mut self.table.state);
+        if self.should_draw_bindings {
+            self.draw_bindings(f, area);
+        }
+        if self.should_draw_policy {
+            self.policy.draw(f, area);
+        }
         if let Some(n) = &self.notif {
             n.draw(f, area);
         }
@@ -133,6 +280,9 @@ where
         if self.should_open_files {
             self.explorer.draw(f, area);
         }
+        if let Some((_, form)) = &self.shovel_form {
+            form.draw(f, area);
+        }
         if self.should_show_help {
             Help::new(HELP).draw(f, area);
         }
@@ -146,12 +296,27 @@ where
 {
     fn handle_key(&mut self, key: Key) {
         self.notif = None;
+        if let Some((info, mut form)) = self.shovel_form.take() {
+            let keep_open = match form.handle_key(key) {
+                FormAction::Submit => !self.submit_shovel_form(&info, &form),
+                FormAction::Cancel => false,
+                FormAction::None => true,
+            };
+            if keep_open {
+                self.shovel_form = Some((info, form));
+            }
+            return;
+        }
         match key {
             Key::Char('j') => {
                 if self.should_confirm {
                     self.confirmation.next();
                 } else if self.should_open_files {
                     self.explorer.next();
+                } else if self.should_draw_bindings {
+                    self.bindings_table.next();
+                } else if self.should_draw_policy {
+                    self.policy.next();
                 } else {
                     self.table.next();
                 }
@@ -161,6 +326,10 @@ where
                     self.confirmation.previous();
                 } else if self.should_open_files {
                     self.explorer.previous();
+                } else if self.should_draw_bindings {
+                    self.bindings_table.previous();
+                } else if self.should_draw_policy {
+                    self.policy.previous();
                 } else {
                     self.table.previous();
                 }
@@ -198,6 +367,18 @@ where
                     self.should_confirm = true;
                 }
             }
+            Key::Char('b') => {
+                self.should_draw_bindings = !self.should_draw_bindings && self.fetch_bindings();
+            }
+            Key::Char('i') => {
+                if !self.should_draw_policy {
+                    self.fetch_policy_details();
+                }
+                self.should_draw_policy = !self.should_draw_policy;
+            }
+            Key::Char('s') => {
+                self.open_shovel_form();
+            }
             Key::Char('f') => {
                 self.should_open_files = !self.should_open_files;
             }
@@ -245,4 +426,8 @@ where
             self.table.data = DataContainer { entries: d };
         }
     }
+
+    fn is_capturing_input(&self) -> bool {
+        self.shovel_form.is_some()
+    }
 }
@@ -0,0 +1,290 @@
+use super::{draw_error, Drawable, StatefulPane};
+use crate::{
+    models::{ShovelDefinition, ShovelStatus},
+    widgets::{
+        confirmation::ConfirmationBox,
+        form::{Form, FormAction},
+        help::Help,
+        notif::Notification,
+    },
+    DataContainer, Datatable, ManagementClient, Rowable,
+};
+
+use std::sync::{mpsc, Arc};
+
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Cell, Row, Table},
+    Frame,
+};
+
+const HELP: &str = "The Shovels tab lists the status of every \
+shovel. Passwords in URIs are masked. Running shovels are green, \
+starting ones yellow and terminated ones red.
+
+Keys:
+  - h: previous tab
+  - l: next tab
+  - k: previous row
+  - j: next row
+  - n: create a dynamic shovel
+  - d: delete selected dynamic shovel
+  - ?: close the help menu
+
+Use amqp:// for the local broker's default vhost and \
+amqp:///vhost for another vhost. Queues can also be \
+shoveled straight from the Queues tab with s.";
+
+pub struct ShovelsPane<'a, M>
+where
+    M: ManagementClient,
+{
+    table: Datatable<ShovelStatus>,
+    confirmation: ConfirmationBox<'a>,
+    data_chan: mpsc::Receiver<Result<Vec<ShovelStatus>, String>>,
+    client: Arc<M>,
+    /// Why the shovels couldn't be fetched, if they couldn't.
+    error: Option<String>,
+    form: Option<Form>,
+    notif: Option<Notification>,
+    should_show_help: bool,
+    should_confirm: bool,
+}
+
+impl<'a, M> ShovelsPane<'a, M>
+where
+    M: ManagementClient,
+{
+    pub fn new(
+        client: Arc<M>,
+        data_chan: mpsc::Receiver<Result<Vec<ShovelStatus>, String>>,
+    ) -> Self {
+        Self {
+            table: Datatable::default(),
+            confirmation: ConfirmationBox::default(),
+            data_chan,
+            client: Arc::clone(&client),
+            error: None,
+            form: None,
+            notif: None,
+            should_show_help: false,
+            should_confirm: false,
+        }
+    }
+
+    fn selected(&self) -> Option<&ShovelStatus> {
+        self.table
+            .state
+            .selected()
+            .and_then(|i| self.table.data.get().get(i))
+    }
+
+    fn refresh(&mut self) {
+        match self.client.get_shovels() {
+            Ok(d) => {
+                self.table.data.set(d);
+                self.error = None;
+            }
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        }
+        let len = self.table.data.get().len();
+        if let Some(i) = self.table.state.selected() {
+            if i >= len {
+                self.table.state.select(len.checked_sub(1));
+            }
+        }
+    }
+
+    fn open_form(&mut self) {
+        self.form = Some(
+            Form::new("New dynamic shovel")
+                .field("Name", "")
+                .field("Vhost", "/")
+                .field("Source URI", "amqp://")
+                .field("Source queue", "")
+                .field("Destination URI", "amqp://")
+                .field("Destination queue", ""),
+        );
+    }
+
+    /// Saves the form. Returns false if the form
+    /// should stay open so the input can be fixed.
+    fn submit_form(&mut self, form: &Form) -> bool {
+        if form.value(0).is_empty() || form.value(3).is_empty() {
+            self.notif = Some(Notification::new(
+                "Name and source queue are required".to_string(),
+            ));
+            return false;
+        }
+        let param = ShovelDefinition::queue_to_queue(
+            form.value(2),
+            form.value(3),
+            form.value(4),
+            form.value(5),
+        )
+        .into_parameter(form.value(1), form.value(0));
+        match self.client.put_parameter(&param) {
+            Ok(_) => {
+                self.refresh();
+                self.notif = Some(Notification::new("Shovel created!".to_string()));
+                true
+            }
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                false
+            }
+        }
+    }
+}
+
+impl<M, B> Drawable<B> for ShovelsPane<'_, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn draw(&mut self, f: &mut Frame<B>, area: Rect) {
+        let data = self.table.data.get();
+        let rects = Layout::default()
+            .constraints([Constraint::Percentage(100)].as_ref())
+            .margin(1)
+            .split(area);
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let normal_style = Style::default();
+        let header_literals = ShovelStatus::headers();
+        let header_cells = header_literals
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Green)));
+        let header = Row::new(header_cells)
+            .style(normal_style)
+            .height(1)
+            .bottom_margin(1);
+        let rows = data.iter().map(|r| {
+            let style = match r.state.as_str() {
+                "running" => Style::default().fg(Color::Green),
+                "starting" => Style::default().fg(Color::Yellow),
+                "terminated" => Style::default().fg(Color::Red),
+                _ => Style::default(),
+            };
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).style(style).bottom_margin(1)
+        });
+        let t = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title("Shovels"))
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(15),
+                Constraint::Percentage(10),
+                Constraint::Percentage(10),
+                Constraint::Percentage(27),
+                Constraint::Percentage(27),
+                Constraint::Percentage(11),
+            ]);
+        match &self.error {
+            Some(e) => draw_error(f, rects[0], "Shovels", e),
+            None => f.render_stateful_widget(t, rects[0], &mut self.table.state),
+        }
+        if self.should_confirm {
+            self.confirmation.draw(f, area);
+        }
+        if let Some(form) = &self.form {
+            form.draw(f, area);
+        }
+        if let Some(n) = &self.notif {
+            n.draw(f, area);
+        }
+        if self.should_show_help {
+            Help::new(HELP).draw(f, area);
+        }
+    }
+}
+
+impl<'a, M, B> StatefulPane<B> for ShovelsPane<'a, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn handle_key(&mut self, key: Key) {
+        self.notif = None;
+        if let Some(mut form) = self.form.take() {
+            let keep_open = match form.handle_key(key) {
+                FormAction::Submit => !self.submit_form(&form),
+                FormAction::Cancel => false,
+                FormAction::None => true,
+            };
+            if keep_open {
+                self.form = Some(form);
+            }
+            return;
+        }
+        match key {
+            Key::Char('j') => {
+                if self.should_confirm {
+                    self.confirmation.next();
+                } else {
+                    self.table.next();
+                }
+            }
+            Key::Char('k') => {
+                if self.should_confirm {
+                    self.confirmation.previous();
+                } else {
+                    self.table.previous();
+                }
+            }
+            Key::Char('n') => {
+                self.open_form();
+            }
+            Key::Char('d') => match self.selected() {
+                Some(s) if s.t == "dynamic" => self.should_confirm = true,
+                Some(_) => {
+                    self.notif = Some(Notification::new(
+                        "Static shovels are configured on the node".to_string(),
+                    ));
+                }
+                None => {}
+            },
+            Key::Char('\n') if self.should_confirm => {
+                if self.confirmation.is_confirmed() {
+                    if let Some(s) = self.selected() {
+                        let msg = match self.client.delete_parameter("shovel", &s.vhost, &s.name) {
+                            Ok(_) => "Shovel deleted!".to_string(),
+                            Err(e) => e,
+                        };
+                        self.refresh();
+                        self.notif = Some(Notification::new(msg));
+                    }
+                }
+                self.confirmation.reset();
+                self.should_confirm = false;
+            }
+            Key::Char('?') => {
+                self.should_show_help = !self.should_show_help;
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self) {
+        match self.data_chan.try_iter().next() {
+            Some(Ok(entries)) => {
+                self.table.data = DataContainer { entries };
+                self.error = None;
+            }
+            Some(Err(e)) => self.error = Some(e),
+            None => {}
+        }
+    }
+
+    fn is_capturing_input(&self) -> bool {
+        self.form.is_some()
+    }
+}
@@ -0,0 +1,214 @@
+use super::{Drawable, StatefulPane};
+use crate::{
+    topology::{NodeKind, Topology, TopologyNode},
+    widgets::help::Help,
+    Datatable, ManagementClient,
+};
+
+use std::sync::{mpsc, Arc};
+
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Cell, Row, Table},
+    Frame,
+};
+
+const HELP: &str = "The Topology tab draws how exchanges, \
+exchange-to-exchange bindings and queues connect within a vhost.
+
+Exchanges with no outbound bindings are yellow and queues \
+with nothing routing into them are red.
+
+Keys:
+  - h: previous tab
+  - l: next tab
+  - k: previous node
+  - j: next node
+  - v: switch to the next vhost
+  - return: focus on the selected exchange
+  - backspace: clear focus
+  - ?: close the help menu";
+
+pub struct TopologyPane {
+    topology: Topology,
+    table: Datatable<TopologyNode>,
+    data_chan: mpsc::Receiver<Topology>,
+    vhost: Option<String>,
+    focus: Option<String>,
+    should_show_help: bool,
+}
+
+impl TopologyPane {
+    pub fn new<M>(client: Arc<M>, data_chan: mpsc::Receiver<Topology>) -> Self
+    where
+        M: ManagementClient,
+    {
+        // Left empty if this fails, the data thread fills it in.
+        let topology = Topology::new(
+            client.get_exchange_overview().unwrap_or_default(),
+            client.get_queues_info().unwrap_or_default(),
+            client.get_bindings().unwrap_or_default(),
+        );
+        let mut pane = Self {
+            topology,
+            table: Datatable::default(),
+            data_chan,
+            vhost: None,
+            focus: None,
+            should_show_help: false,
+        };
+        pane.vhost = pane.topology.vhosts().into_iter().next();
+        pane.rebuild();
+        pane
+    }
+
+    /// Recomputes the graph lines for the current vhost and focus,
+    /// keeping the selection where it was when possible.
+    fn rebuild(&mut self) {
+        let nodes = match &self.vhost {
+            Some(v) => self.topology.graph(v, self.focus.as_deref()),
+            None => Vec::new(),
+        };
+        let selected = match self.table.state.selected() {
+            Some(i) if i < nodes.len() => Some(i),
+            _ if nodes.is_empty() => None,
+            _ => Some(0),
+        };
+        self.table = Datatable::new(nodes);
+        self.table.state.select(selected);
+    }
+
+    fn next_vhost(&mut self) {
+        let vhosts = self.topology.vhosts();
+        if vhosts.is_empty() {
+            return;
+        }
+        let i = self
+            .vhost
+            .as_ref()
+            .and_then(|v| vhosts.iter().position(|o| o == v))
+            .map(|i| (i + 1) % vhosts.len())
+            .unwrap_or(0);
+        self.vhost = Some(vhosts[i].clone());
+        self.focus = None;
+        self.table.state.select(None);
+        self.rebuild();
+    }
+
+    fn node_line(node: &TopologyNode) -> Spans<'static> {
+        let mut spans = vec![Span::styled(
+            node.prefix.clone(),
+            Style::default().fg(Color::DarkGray),
+        )];
+        if let Some(k) = &node.routing_key {
+            let key = if k.is_empty() { "(none)" } else { k.as_str() };
+            spans.push(Span::styled(
+                format!("[{}] ", key),
+                Style::default().fg(Color::Cyan),
+            ));
+            spans.push(Span::raw("→ "));
+        }
+        let style = match (node.kind, node.dangling) {
+            (NodeKind::Exchange, true) => Style::default().fg(Color::Yellow),
+            (NodeKind::Queue, true) => Style::default().fg(Color::Red),
+            _ => Style::default(),
+        };
+        let label = match node.kind {
+            NodeKind::Exchange => format!("⇉ {} ({})", node.name, node.t),
+            NodeKind::Queue => format!("▤ {} ({})", node.name, node.t),
+        };
+        spans.push(Span::styled(label, style.add_modifier(Modifier::BOLD)));
+        if node.cycle {
+            spans.push(Span::styled(
+                " ↺ cycle",
+                Style::default().fg(Color::Magenta),
+            ));
+        }
+        Spans::from(spans)
+    }
+}
+
+impl<B> Drawable<B> for TopologyPane
+where
+    B: Backend,
+{
+    fn draw(&mut self, f: &mut Frame<B>, area: Rect) {
+        let rects = Layout::default()
+            .constraints([Constraint::Percentage(100)].as_ref())
+            .margin(1)
+            .split(area);
+        let rows = self
+            .table
+            .data
+            .get()
+            .iter()
+            .map(|n| Row::new(vec![Cell::from(Self::node_line(n))]));
+        let title = match (&self.vhost, &self.focus) {
+            (Some(v), Some(e)) => format!("Topology - vhost {} - focus {}", v, e),
+            (Some(v), None) => format!("Topology - vhost {}", v),
+            (None, _) => "Topology".to_owned(),
+        };
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let t = Table::new(rows)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[Constraint::Percentage(100)]);
+        f.render_stateful_widget(t, rects[0], &mut self.table.state);
+        if self.should_show_help {
+            Help::new(HELP).draw(f, area);
+        }
+    }
+}
+
+impl<B> StatefulPane<B> for TopologyPane
+where
+    B: Backend,
+{
+    fn handle_key(&mut self, key: Key) {
+        match key {
+            Key::Char('j') => {
+                self.table.next();
+            }
+            Key::Char('k') => {
+                self.table.previous();
+            }
+            Key::Char('v') => {
+                self.next_vhost();
+            }
+            Key::Char('\n') => {
+                if let Some(i) = self.table.state.selected() {
+                    let node = &self.table.data.get()[i];
+                    if node.kind == NodeKind::Exchange {
+                        self.focus = Some(node.name.clone());
+                        self.table.state.select(Some(0));
+                        self.rebuild();
+                    }
+                }
+            }
+            Key::Backspace if self.focus.is_some() => {
+                self.focus = None;
+                self.table.state.select(Some(0));
+                self.rebuild();
+            }
+            Key::Char('?') => {
+                self.should_show_help = !self.should_show_help;
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self) {
+        if let Some(d) = self.data_chan.try_iter().next() {
+            self.topology = d;
+            if self.vhost.is_none() {
+                self.vhost = self.topology.vhosts().into_iter().next();
+            }
+            self.rebuild();
+        }
+    }
+}
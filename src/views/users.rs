@@ -0,0 +1,727 @@
+use super::{centered_rect, draw_error, Drawable, StatefulPane};
+use crate::{
+    models::{Permission, TopicPermission, User, UserUpdate},
+    permissions::{self, Operation, Verdict},
+    widgets::{
+        confirmation::ConfirmationBox,
+        form::{Form, FormAction},
+        help::Help,
+        notif::Notification,
+    },
+    Datatable, ManagementClient, Rowable,
+};
+
+use std::sync::{mpsc, Arc};
+
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, Wrap},
+    Frame,
+};
+
+const HELP: &str = "The Users tab is where you administer users \
+and their permissions.
+
+Keys:
+  - h: previous tab
+  - l: next tab
+  - k: previous row
+  - j: next row
+  - n: create a user, or a permission when drilled down
+  - t: change tags of selected user
+  - d: delete selected user or permission
+  - return: open permissions drilldown for selected user
+  - tab: switch between vhost and topic permissions in drilldown
+  - e: edit selected permission in drilldown
+  - c: check whether a user can configure, write or read a resource,
+       any key closes the result
+  - esc: close drilldown
+  - ?: close the help menu
+
+Tags are comma separated, e.g. management,policymaker. The \
+checker evaluates permission regexes locally, leave the resource \
+empty to check the default exchange.";
+
+pub type UsersData = (Vec<User>, Vec<Permission>, Vec<TopicPermission>);
+
+/// Reads the pane's data. Listing users needs the
+/// administrator tag.
+pub fn fetch<M: ManagementClient>(client: &M) -> Result<UsersData, String> {
+    Ok((
+        client.get_users()?,
+        client.get_permissions()?,
+        client.get_topic_permissions()?,
+    ))
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Users,
+    Permissions,
+    TopicPermissions,
+}
+
+enum FormKind {
+    NewUser,
+    Tags,
+    Permission,
+    TopicPermission,
+    Check,
+}
+
+/// Topic permissions of the same user and vhost as `removed`, on
+/// other exchanges. The API can only delete all of a user's topic
+/// permissions in a vhost at once, so these are put back after.
+fn kept_topic_permissions<'a>(
+    all: &'a [TopicPermission],
+    removed: &TopicPermission,
+) -> Vec<&'a TopicPermission> {
+    all.iter()
+        .filter(|p| p.user == removed.user && p.vhost == removed.vhost)
+        .filter(|p| p.exchange != removed.exchange)
+        .collect()
+}
+
+pub struct UsersPane<'a, M>
+where
+    M: ManagementClient,
+{
+    table: Datatable<User>,
+    permissions: Vec<Permission>,
+    topic_permissions: Vec<TopicPermission>,
+    perm_table: Datatable<Permission>,
+    topic_table: Datatable<TopicPermission>,
+    confirmation: ConfirmationBox<'a>,
+    data_chan: mpsc::Receiver<Result<UsersData, String>>,
+    client: Arc<M>,
+    /// Why the users couldn't be fetched, if they couldn't.
+    error: Option<String>,
+    form: Option<(FormKind, Form)>,
+    focus: Focus,
+    /// Result of the last permission check and the
+    /// question it answers, shown until dismissed.
+    verdict: Option<(String, Verdict)>,
+    notif: Option<Notification>,
+    should_show_help: bool,
+    should_confirm: bool,
+}
+
+impl<'a, M> UsersPane<'a, M>
+where
+    M: ManagementClient,
+{
+    pub fn new(client: Arc<M>, data_chan: mpsc::Receiver<Result<UsersData, String>>) -> Self {
+        Self {
+            table: Datatable::default(),
+            permissions: Vec::new(),
+            topic_permissions: Vec::new(),
+            perm_table: Datatable::default(),
+            topic_table: Datatable::default(),
+            confirmation: ConfirmationBox::default(),
+            data_chan,
+            client: Arc::clone(&client),
+            error: None,
+            form: None,
+            focus: Focus::Users,
+            verdict: None,
+            notif: None,
+            should_show_help: false,
+            should_confirm: false,
+        }
+    }
+
+    fn selected_user(&self) -> Option<&User> {
+        self.table
+            .state
+            .selected()
+            .and_then(|i| self.table.data.get().get(i))
+    }
+
+    fn selected_permission(&self) -> Option<&Permission> {
+        self.perm_table
+            .state
+            .selected()
+            .and_then(|i| self.perm_table.data.get().get(i))
+    }
+
+    fn selected_topic_permission(&self) -> Option<&TopicPermission> {
+        self.topic_table
+            .state
+            .selected()
+            .and_then(|i| self.topic_table.data.get().get(i))
+    }
+
+    fn refresh(&mut self) {
+        let data = fetch(&*self.client);
+        self.set_data(data);
+    }
+
+    fn set_data(&mut self, data: Result<UsersData, String>) {
+        let (users, permissions, topic_permissions) = match data {
+            Ok(d) => d,
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        };
+        self.error = None;
+        self.table.data.set(users);
+        let len = self.table.data.get().len();
+        if let Some(i) = self.table.state.selected() {
+            if i >= len {
+                self.table.state.select(len.checked_sub(1));
+            }
+        }
+        self.permissions = permissions;
+        self.topic_permissions = topic_permissions;
+        self.load_drilldown();
+    }
+
+    /// Narrows the permission tables down to the selected user.
+    fn load_drilldown(&mut self) {
+        let name = match self.selected_user() {
+            Some(u) => u.name.clone(),
+            None => return,
+        };
+        let perms: Vec<Permission> = self
+            .permissions
+            .iter()
+            .filter(|p| p.user == name)
+            .cloned()
+            .collect();
+        let topics: Vec<TopicPermission> = self
+            .topic_permissions
+            .iter()
+            .filter(|p| p.user == name)
+            .cloned()
+            .collect();
+        let perm_sel = self.perm_table.state.selected();
+        let topic_sel = self.topic_table.state.selected();
+        self.perm_table = Datatable::new(perms);
+        self.topic_table = Datatable::new(topics);
+        let clamp = |sel: Option<usize>, len: usize| match sel {
+            Some(i) if i < len => Some(i),
+            _ if len > 0 => Some(0),
+            _ => None,
+        };
+        self.perm_table
+            .state
+            .select(clamp(perm_sel, self.perm_table.data.get().len()));
+        self.topic_table
+            .state
+            .select(clamp(topic_sel, self.topic_table.data.get().len()));
+    }
+
+    fn open_form(&mut self, kind: FormKind) {
+        let user = match self.selected_user() {
+            Some(u) => u.name.clone(),
+            None if matches!(kind, FormKind::NewUser | FormKind::Check) => String::new(),
+            None => return,
+        };
+        let form = match kind {
+            FormKind::NewUser => Form::new("New user")
+                .field("Name", "")
+                .masked_field("Password")
+                .field("Password hash (instead of password)", "")
+                .field("Tags", ""),
+            FormKind::Tags => {
+                let tags = self
+                    .selected_user()
+                    .map(|u| u.tags.join(","))
+                    .unwrap_or_default();
+                Form::new(&format!("Tags for {}", user)).field("Tags", &tags)
+            }
+            FormKind::Permission => {
+                let (vhost, configure, write, read) = match self.selected_permission() {
+                    Some(p) => (
+                        p.vhost.as_str(),
+                        p.configure.as_str(),
+                        p.write.as_str(),
+                        p.read.as_str(),
+                    ),
+                    None => ("/", ".*", ".*", ".*"),
+                };
+                Form::new(&format!("Permissions for {}", user))
+                    .field("Vhost", vhost)
+                    .field("Configure regex", configure)
+                    .field("Write regex", write)
+                    .field("Read regex", read)
+            }
+            FormKind::TopicPermission => {
+                let (vhost, exchange, write, read) = match self.selected_topic_permission() {
+                    Some(p) => (
+                        p.vhost.as_str(),
+                        p.exchange.as_str(),
+                        p.write.as_str(),
+                        p.read.as_str(),
+                    ),
+                    None => ("/", "amq.topic", ".*", ".*"),
+                };
+                Form::new(&format!("Topic permissions for {}", user))
+                    .field("Vhost", vhost)
+                    .field("Exchange", exchange)
+                    .field("Write regex", write)
+                    .field("Read regex", read)
+            }
+            FormKind::Check => Form::new("Check permission")
+                .field("User", &user)
+                .field("Vhost", "/")
+                .field("Operation (configure, write, read)", "read")
+                .field("Resource (queue or exchange name)", ""),
+        };
+        self.form = Some((kind, form));
+    }
+
+    /// Saves the form. Returns false if the form
+    /// should stay open so the input can be fixed.
+    fn submit_form(&mut self, kind: &FormKind, form: &Form) -> bool {
+        let user = self
+            .selected_user()
+            .map(|u| u.name.clone())
+            .unwrap_or_default();
+        let res = match kind {
+            FormKind::NewUser => {
+                let password = form.value(1);
+                let hash = form.value(2);
+                if form.value(0).is_empty() {
+                    Err("Name is required".to_string())
+                } else if password.is_empty() && hash.is_empty() {
+                    Err("Password or password hash is required".to_string())
+                } else {
+                    self.client.put_user(&UserUpdate {
+                        name: form.value(0).to_string(),
+                        password: Some(password.to_string()).filter(|p| !p.is_empty()),
+                        password_hash: Some(hash.to_string()).filter(|h| !h.is_empty()),
+                        hashing_algorithm: None,
+                        tags: form.value(3).to_string(),
+                    })
+                }
+            }
+            FormKind::Tags => match self.selected_user() {
+                Some(u) => self.client.put_user(&UserUpdate::retag(u, form.value(0))),
+                None => Ok(()),
+            },
+            FormKind::Permission => self.client.put_permission(&Permission {
+                user,
+                vhost: form.value(0).to_string(),
+                configure: form.value(1).to_string(),
+                write: form.value(2).to_string(),
+                read: form.value(3).to_string(),
+            }),
+            FormKind::TopicPermission => self.client.put_topic_permission(&TopicPermission {
+                user,
+                vhost: form.value(0).to_string(),
+                exchange: form.value(1).to_string(),
+                write: form.value(2).to_string(),
+                read: form.value(3).to_string(),
+            }),
+            FormKind::Check => return self.check(form),
+        };
+        match res {
+            Ok(_) => {
+                self.refresh();
+                self.notif = Some(Notification::new("Saved!".to_string()));
+                true
+            }
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                false
+            }
+        }
+    }
+
+    /// Runs the permission checker. Returns false if the
+    /// form should stay open so the input can be fixed.
+    fn check(&mut self, form: &Form) -> bool {
+        let op = match form.value(2).parse::<Operation>() {
+            Ok(op) => op,
+            Err(e) => {
+                self.notif = Some(Notification::new(e));
+                return false;
+            }
+        };
+        let (user, vhost, resource) = (form.value(0), form.value(1), form.value(3));
+        let resource_name = if resource.is_empty() {
+            "(AMQP DEFAULT)"
+        } else {
+            resource
+        };
+        let question = format!("Can {} {} {} on vhost {}?", user, op, resource_name, vhost);
+        let verdict = permissions::check(
+            self.table.data.get(),
+            &self.permissions,
+            user,
+            vhost,
+            op,
+            resource,
+        );
+        self.verdict = Some((question, verdict));
+        true
+    }
+
+    fn draw_verdict<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let (question, verdict) = match &self.verdict {
+            Some(v) => v,
+            None => return,
+        };
+        let (answer, color) = if verdict.allowed {
+            ("ALLOWED", Color::Green)
+        } else {
+            ("DENIED", Color::Red)
+        };
+        let mut lines = vec![
+            Spans::from(Span::raw(question.clone())),
+            Spans::from(Span::styled(
+                answer,
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(Span::raw("")),
+        ];
+        lines.extend(
+            verdict
+                .explanation
+                .iter()
+                .map(|l| Spans::from(Span::raw(l.clone()))),
+        );
+        let pg = Paragraph::new(Text::from(lines))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(color))
+                    .title("Permission check"),
+            )
+            .wrap(Wrap { trim: true });
+        let pop_area = centered_rect(60, 40, area);
+        f.render_widget(Clear, pop_area);
+        f.render_widget(pg, pop_area);
+    }
+
+    fn delete_selected(&mut self) {
+        let res = match self.focus {
+            Focus::Users => self
+                .selected_user()
+                .map(|u| self.client.delete_user(&u.name)),
+            Focus::Permissions => self
+                .selected_permission()
+                .map(|p| self.client.delete_permission(&p.vhost, &p.user)),
+            Focus::TopicPermissions => self.selected_topic_permission().map(|p| {
+                let kept = kept_topic_permissions(&self.topic_permissions, p);
+                self.client
+                    .delete_topic_permission(&p.vhost, &p.user)
+                    .and_then(|_| {
+                        kept.into_iter()
+                            .try_for_each(|k| self.client.put_topic_permission(k))
+                    })
+            }),
+        };
+        if let Some(r) = res {
+            let msg = match r {
+                Ok(_) => "Deleted!".to_string(),
+                Err(e) => e,
+            };
+            self.refresh();
+            self.notif = Some(Notification::new(msg));
+        }
+    }
+
+    fn draw_drilldown<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let pop_area = centered_rect(70, 70, area);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(pop_area);
+        let user = self
+            .selected_user()
+            .map(|u| u.name.clone())
+            .unwrap_or_default();
+        f.render_widget(Clear, pop_area);
+        draw_permission_table(
+            f,
+            chunks[0],
+            &format!("Permissions - {}", user),
+            Permission::headers(),
+            &mut self.perm_table,
+            self.focus == Focus::Permissions,
+        );
+        draw_permission_table(
+            f,
+            chunks[1],
+            &format!("Topic Permissions - {}", user),
+            TopicPermission::headers(),
+            &mut self.topic_table,
+            self.focus == Focus::TopicPermissions,
+        );
+    }
+}
+
+fn draw_permission_table<B, T>(
+    f: &mut Frame<B>,
+    area: Rect,
+    title: &str,
+    headers: [&str; 4],
+    table: &mut Datatable<T>,
+    focused: bool,
+) where
+    B: Backend,
+    T: Rowable,
+{
+    let header_cells = headers
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow)));
+    let header = Row::new(header_cells)
+        .style(Style::default())
+        .height(1)
+        .bottom_margin(1);
+    let rows = table.data.get().iter().map(|r| {
+        let vecd = r.to_row();
+        let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+        Row::new(cells)
+    });
+    let (border_style, selected_style) = if focused {
+        (
+            Style::default().fg(Color::Yellow),
+            Style::default().add_modifier(Modifier::REVERSED),
+        )
+    } else {
+        (Style::default(), Style::default())
+    };
+    let t = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style)
+                .title(title.to_string()),
+        )
+        .highlight_style(selected_style)
+        .highlight_symbol(">> ")
+        .widths(&[
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+        ]);
+    f.render_stateful_widget(t, area, &mut table.state);
+}
+
+impl<M, B> Drawable<B> for UsersPane<'_, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn draw(&mut self, f: &mut Frame<B>, area: Rect) {
+        let data = self.table.data.get();
+        let rects = Layout::default()
+            .constraints([Constraint::Percentage(100)].as_ref())
+            .margin(1)
+            .split(area);
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let normal_style = Style::default();
+        let header_literals = User::headers();
+        let header_cells = header_literals
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Green)));
+        let header = Row::new(header_cells)
+            .style(normal_style)
+            .height(1)
+            .bottom_margin(1);
+        let permissions = &self.permissions;
+        let rows = data.iter().map(|r| {
+            let vecd = r.to_row_with(permissions);
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).bottom_margin(1)
+        });
+        let t = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title("Users"))
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(30),
+                Constraint::Percentage(30),
+                Constraint::Percentage(40),
+            ]);
+        match &self.error {
+            Some(e) => draw_error(f, rects[0], "Users", e),
+            None => f.render_stateful_widget(t, rects[0], &mut self.table.state),
+        }
+        if self.focus != Focus::Users {
+            self.draw_drilldown(f, area);
+        }
+        if self.should_confirm {
+            self.confirmation.draw(f, area);
+        }
+        self.draw_verdict(f, area);
+        if let Some((_, form)) = &self.form {
+            form.draw(f, area);
+        }
+        if let Some(n) = &self.notif {
+            n.draw(f, area);
+        }
+        if self.should_show_help {
+            Help::new(HELP).draw(f, area);
+        }
+    }
+}
+
+impl<'a, M, B> StatefulPane<B> for UsersPane<'a, M>
+where
+    M: ManagementClient,
+    B: Backend,
+{
+    fn handle_key(&mut self, key: Key) {
+        self.notif = None;
+        if let Some((kind, mut form)) = self.form.take() {
+            let keep_open = match form.handle_key(key) {
+                FormAction::Submit => !self.submit_form(&kind, &form),
+                FormAction::Cancel => false,
+                FormAction::None => true,
+            };
+            if keep_open {
+                self.form = Some((kind, form));
+            }
+            return;
+        }
+        if self.verdict.take().is_some() {
+            return;
+        }
+        match key {
+            Key::Char('j') => {
+                if self.should_confirm {
+                    self.confirmation.next();
+                } else {
+                    match self.focus {
+                        Focus::Users => {
+                            self.table.next();
+                            self.load_drilldown();
+                        }
+                        Focus::Permissions => self.perm_table.next(),
+                        Focus::TopicPermissions => self.topic_table.next(),
+                    }
+                }
+            }
+            Key::Char('k') => {
+                if self.should_confirm {
+                    self.confirmation.previous();
+                } else {
+                    match self.focus {
+                        Focus::Users => {
+                            self.table.previous();
+                            self.load_drilldown();
+                        }
+                        Focus::Permissions => self.perm_table.previous(),
+                        Focus::TopicPermissions => self.topic_table.previous(),
+                    }
+                }
+            }
+            Key::Char('n') => match self.focus {
+                Focus::Users => self.open_form(FormKind::NewUser),
+                Focus::Permissions => {
+                    self.perm_table.state.select(None);
+                    self.open_form(FormKind::Permission);
+                }
+                Focus::TopicPermissions => {
+                    self.topic_table.state.select(None);
+                    self.open_form(FormKind::TopicPermission);
+                }
+            },
+            Key::Char('c') => {
+                self.open_form(FormKind::Check);
+            }
+            Key::Char('t') if self.focus == Focus::Users => {
+                self.open_form(FormKind::Tags);
+            }
+            Key::Char('e') => match self.focus {
+                Focus::Users => {}
+                Focus::Permissions => {
+                    if self.selected_permission().is_some() {
+                        self.open_form(FormKind::Permission);
+                    }
+                }
+                Focus::TopicPermissions => {
+                    if self.selected_topic_permission().is_some() {
+                        self.open_form(FormKind::TopicPermission);
+                    }
+                }
+            },
+            Key::Char('d') => {
+                self.should_confirm = match self.focus {
+                    Focus::Users => self.selected_user().is_some(),
+                    Focus::Permissions => self.selected_permission().is_some(),
+                    Focus::TopicPermissions => self.selected_topic_permission().is_some(),
+                };
+            }
+            Key::Char('\n') => {
+                if self.should_confirm {
+                    if self.confirmation.is_confirmed() {
+                        self.delete_selected();
+                    }
+                    self.confirmation.reset();
+                    self.should_confirm = false;
+                } else if self.focus == Focus::Users && self.selected_user().is_some() {
+                    self.load_drilldown();
+                    self.focus = Focus::Permissions;
+                }
+            }
+            Key::Char('\t') => {
+                self.focus = match self.focus {
+                    Focus::Users => Focus::Users,
+                    Focus::Permissions => Focus::TopicPermissions,
+                    Focus::TopicPermissions => Focus::Permissions,
+                };
+            }
+            Key::Esc => {
+                self.focus = Focus::Users;
+            }
+            Key::Char('?') => {
+                self.should_show_help = !self.should_show_help;
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self) {
+        if let Some(d) = self.data_chan.try_iter().next() {
+            self.set_data(d);
+        }
+    }
+
+    fn is_capturing_input(&self) -> bool {
+        self.form.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(user: &str, vhost: &str, exchange: &str) -> TopicPermission {
+        TopicPermission {
+            user: user.to_string(),
+            vhost: vhost.to_string(),
+            exchange: exchange.to_string(),
+            write: ".*".to_string(),
+            read: ".*".to_string(),
+        }
+    }
+
+    #[test]
+    fn deleting_one_exchange_keeps_the_others() {
+        let all = vec![
+            topic("app", "/", "amq.topic"),
+            topic("app", "/", "events"),
+            topic("app", "other", "events"),
+            topic("bob", "/", "events"),
+        ];
+        let kept = kept_topic_permissions(&all, &all[0]);
+        let kept: Vec<(&str, &str, &str)> = kept
+            .iter()
+            .map(|p| (p.user.as_str(), p.vhost.as_str(), p.exchange.as_str()))
+            .collect();
+        assert_eq!(kept, [("app", "/", "events")]);
+    }
+}
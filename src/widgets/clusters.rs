@@ -0,0 +1,93 @@
+use crate::{views::centered_rect, Datatable};
+
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Span,
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, Wrap},
+    Frame,
+};
+
+/// Popup listing the profiles of the config file, with the
+/// one currently connected to marked.
+pub struct ClusterPicker {
+    table: Datatable<String>,
+    current: Option<String>,
+    error: Option<String>,
+}
+
+impl ClusterPicker {
+    pub fn new(profiles: Vec<String>, current: Option<&str>) -> Self {
+        let selected = current
+            .and_then(|c| profiles.iter().position(|p| p == c))
+            .unwrap_or(0);
+        let empty = profiles.is_empty();
+        let mut table = Datatable::new(profiles);
+        if !empty {
+            table.state.select(Some(selected));
+        }
+        Self {
+            table,
+            current: current.map(String::from),
+            error: None,
+        }
+    }
+
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let pop_area = centered_rect(40, 50, area);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(4)])
+            .split(pop_area);
+        let current = self.current.as_deref();
+        let rows = self.table.data.get().iter().map(|p| {
+            let (marker, style) = if Some(p.as_str()) == current {
+                ("* ", Style::default().fg(Color::Green))
+            } else {
+                ("  ", Style::default())
+            };
+            Row::new(vec![Cell::from(format!("{}{}", marker, p)).style(style)])
+        });
+        let t = Table::new(rows)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Clusters (Enter to connect, Esc to close)"),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol(">> ")
+            .widths(&[Constraint::Percentage(100)]);
+        let status = match (&self.error, self.table.data.get().is_empty()) {
+            (Some(e), _) => Span::styled(e.as_str(), Style::default().fg(Color::Red)),
+            (None, true) => Span::raw("No profiles found in the config file."),
+            (None, false) => Span::raw(""),
+        };
+        let status = Paragraph::new(status)
+            .block(Block::default().borders(Borders::ALL))
+            .wrap(Wrap { trim: true });
+        f.render_widget(Clear, pop_area);
+        f.render_stateful_widget(t, chunks[0], &mut self.table.state);
+        f.render_widget(status, chunks[1]);
+    }
+
+    pub fn next(&mut self) {
+        self.table.next();
+    }
+
+    pub fn previous(&mut self) {
+        self.table.previous();
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.table
+            .state
+            .selected()
+            .map(|i| self.table.data.get()[i].as_str())
+    }
+
+    /// Shows why connecting to the selected profile failed.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+}
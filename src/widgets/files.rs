@@ -1,6 +1,6 @@
 use crate::{views::centered_rect, Datatable};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use std::fs;
 
@@ -82,6 +82,11 @@ impl FileNavigator {
         f.render_stateful_widget(t, pop_area, &mut self.file_table.state);
     }
 
+    /// The directory currently being browsed.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
     pub fn next(&mut self) {
         self.file_table.next();
     }
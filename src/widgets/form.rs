@@ -0,0 +1,176 @@
+use crate::views::centered_rect;
+
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+/// Outcome of passing a key to a form.
+#[derive(Debug, PartialEq)]
+pub enum FormAction {
+    None,
+    Submit,
+    Cancel,
+}
+
+pub struct FormField {
+    label: String,
+    value: String,
+    masked: bool,
+}
+
+/// A popup of labelled single line text inputs. While a form is
+/// open it should receive every key press, so the pane owning it
+/// must report that it is capturing input.
+pub struct Form {
+    title: String,
+    fields: Vec<FormField>,
+    selected: usize,
+}
+
+impl Form {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            fields: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn field(mut self, label: &str, value: &str) -> Self {
+        self.fields.push(FormField {
+            label: label.to_string(),
+            value: value.to_string(),
+            masked: false,
+        });
+        self
+    }
+
+    /// Same as `field`, but the value is never drawn.
+    pub fn masked_field(mut self, label: &str) -> Self {
+        self.fields.push(FormField {
+            label: label.to_string(),
+            value: String::new(),
+            masked: true,
+        });
+        self
+    }
+
+    /// Returns the value of the field at index `i`, trimmed unless
+    /// the field is masked. Spaces can be part of a password.
+    pub fn value(&self, i: usize) -> &str {
+        let field = &self.fields[i];
+        if field.masked {
+            &field.value
+        } else {
+            field.value.trim()
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> FormAction {
+        match key {
+            Key::Esc => return FormAction::Cancel,
+            Key::Char('\n') => return FormAction::Submit,
+            Key::Char('\t') | Key::Down => {
+                self.selected = (self.selected + 1) % self.fields.len();
+            }
+            Key::BackTab | Key::Up => {
+                self.selected = if self.selected == 0 {
+                    self.fields.len() - 1
+                } else {
+                    self.selected - 1
+                };
+            }
+            Key::Backspace => {
+                self.fields[self.selected].value.pop();
+            }
+            Key::Char(c) => {
+                self.fields[self.selected].value.push(c);
+            }
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    pub fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let pop_area = centered_rect(60, 60, area);
+        let background = Block::default()
+            .title(Span::styled(
+                self.title.clone(),
+                Style::default().fg(Color::Yellow),
+            ))
+            .borders(Borders::ALL);
+        let mut constraints: Vec<Constraint> =
+            self.fields.iter().map(|_| Constraint::Length(3)).collect();
+        constraints.push(Constraint::Min(0));
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .margin(1)
+            .split(pop_area);
+        f.render_widget(Clear, pop_area);
+        f.render_widget(background, pop_area);
+        for (i, field) in self.fields.iter().enumerate() {
+            let style = if i == self.selected {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            let shown = if field.masked {
+                "*".repeat(field.value.chars().count())
+            } else {
+                field.value.clone()
+            };
+            let mut spans = vec![Span::raw(shown)];
+            if i == self.selected {
+                spans.push(Span::styled(
+                    "_",
+                    Style::default().add_modifier(Modifier::SLOW_BLINK),
+                ));
+            }
+            let input = Paragraph::new(Spans::from(spans)).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(style)
+                    .title(field.label.clone()),
+            );
+            f.render_widget(input, chunks[i]);
+        }
+        let hint = Paragraph::new(Span::styled(
+            "tab: next field  return: submit  esc: cancel",
+            Style::default().fg(Color::DarkGray),
+        ));
+        f.render_widget(hint, chunks[self.fields.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(form: &mut Form, text: &str) {
+        for c in text.chars() {
+            form.handle_key(Key::Char(c));
+        }
+    }
+
+    #[test]
+    fn plain_fields_are_trimmed() {
+        let mut form = Form::new("t").field("Name", "");
+        typed(&mut form, "  alice ");
+        assert_eq!(form.value(0), "alice");
+    }
+
+    #[test]
+    fn masked_fields_are_kept_as_typed() {
+        let mut form = Form::new("t").field("Name", "").masked_field("Password");
+        form.handle_key(Key::Char('\t'));
+        typed(&mut form, " pass word ");
+        assert_eq!(form.value(1), " pass word ");
+    }
+}
@@ -1,5 +1,8 @@
 pub mod chart;
+pub mod clusters;
 pub mod confirmation;
 pub mod files;
+pub mod form;
 pub mod help;
 pub mod notif;
+pub mod policy;
@@ -0,0 +1,87 @@
+use crate::{
+    models::PolicyDetail,
+    views::{centered_rect, draw_error},
+    Datatable, Rowable,
+};
+
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Cell, Clear, Row, Table},
+    Frame,
+};
+
+/// Popup breaking down where each effective argument of a
+/// queue or exchange comes from.
+#[derive(Default)]
+pub struct PolicyPopup {
+    table: Datatable<PolicyDetail>,
+    /// Why the policies couldn't be fetched, shown instead
+    /// of the table.
+    error: Option<String>,
+}
+
+impl PolicyPopup {
+    pub fn new(details: Vec<PolicyDetail>) -> Self {
+        Self {
+            table: Datatable::new(details),
+            error: None,
+        }
+    }
+
+    pub fn failed(error: String) -> Self {
+        Self {
+            table: Datatable::default(),
+            error: Some(error),
+        }
+    }
+
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let pop_area = centered_rect(70, 50, area);
+        f.render_widget(Clear, pop_area);
+        if let Some(e) = &self.error {
+            draw_error(f, pop_area, "Effective Policy", e);
+            return;
+        }
+        let data = self.table.data.get();
+        let header_lits = PolicyDetail::headers();
+        let header_cells = header_lits
+            .iter()
+            .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow)));
+        let header = Row::new(header_cells)
+            .style(Style::default())
+            .height(1)
+            .bottom_margin(1);
+        let rows = data.iter().map(|r| {
+            let vecd = r.to_row();
+            let cells = vecd.iter().map(|c| Cell::from(c.clone()));
+            Row::new(cells).bottom_margin(1)
+        });
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let t = Table::new(rows)
+            .header(header)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Effective Policy"),
+            )
+            .highlight_style(selected_style)
+            .highlight_symbol(">> ")
+            .widths(&[
+                Constraint::Percentage(25),
+                Constraint::Percentage(20),
+                Constraint::Percentage(20),
+                Constraint::Percentage(35),
+            ]);
+        f.render_stateful_widget(t, pop_area, &mut self.table.state);
+    }
+
+    pub fn next(&mut self) {
+        self.table.next();
+    }
+
+    pub fn previous(&mut self) {
+        self.table.previous();
+    }
+}